@@ -10,19 +10,18 @@ with open('README.md') as readme_file:
 
 setup_requirements = ['pytest-runner', ]
 test_requirements = ['pytest>=3', ]
-requirements = []
+requirements = ['numpy', ]
 
 
 setup(
     author="Todd Young",
     author_email='youngmt1@ornl.gov',
-    python_requires='>=3.5',
+    python_requires='>=3.7',
     classifiers=[
         'Development Status :: 2 - Pre-Alpha',
         'Intended Audience :: Developers',
         'License :: OSI Approved :: MIT License',
         'Natural Language :: English',
-        'Programming Language :: Python :: 3.6',
         'Programming Language :: Python :: 3.7',
         'Programming Language :: Python :: 3.8',
     ],
@@ -33,7 +32,9 @@ setup(
     include_package_data=True,
     keywords='tok',
     name='tok',
-    rust_extensions=[RustExtension("tok", "Cargo.toml", debug=False)],
+    rust_extensions=[
+        RustExtension("tok", "Cargo.toml", debug=False, features=["python"])
+    ],
     setup_requires=setup_requirements,
     test_suite='tests',
     tests_require=test_requirements,
@@ -0,0 +1,609 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::count::{self, TermCounts, STREAM};
+use crate::error::{Error, Result};
+use crate::model::{config, Model};
+use crate::normalizer::Normalizer;
+use crate::tokenizer::{SharedTokenizer, Tokenizer};
+use crate::vocab::Vocab;
+
+/// How ids are assigned to corpus terms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdOrder {
+    /// In the order terms are first seen in the corpus
+    #[default]
+    FirstSeen,
+    /// By descending frequency, breaking ties alphabetically
+    Frequency,
+}
+
+/// Options for building a [`Vocab`]
+///
+/// ```no_run
+/// use tok::{IdOrder, Vocab, PAD, UNK};
+///
+/// let vocab = Vocab::builder()
+///     .special_tokens(&[PAD])
+///     .unk_token(UNK)
+///     .min_freq(5)
+///     .max_size(50_000)
+///     .order(IdOrder::Frequency)
+///     .build("corpus.txt")?;
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct VocabBuilder {
+    /// Reserved tokens, assigned the lowest ids in this order
+    specials: Vec<String>,
+    /// Token used for out-of-vocabulary terms
+    unk: Option<String>,
+    /// Terms seen fewer times than this are dropped
+    min_freq: u64,
+    /// Upper bound on the vocabulary size, special tokens included
+    max_size: Option<usize>,
+    /// How ids are assigned to corpus terms
+    order: IdOrder,
+    /// Number of threads used to count files
+    threads: usize,
+    /// Applied to the corpus before splitting, and kept by the built vocabulary
+    normalizer: Normalizer,
+    /// Splits the corpus into terms, and is kept by the built vocabulary
+    tokenizer: SharedTokenizer,
+    /// Splits terms into the entries that are counted
+    model: Model,
+}
+
+impl Default for VocabBuilder {
+    fn default() -> Self {
+        VocabBuilder {
+            specials: Vec::new(),
+            unk: None,
+            min_freq: 1,
+            max_size: None,
+            order: IdOrder::default(),
+            threads: 1,
+            normalizer: Normalizer::default(),
+            tokenizer: SharedTokenizer::default(),
+            model: Model::Word,
+        }
+    }
+}
+
+impl VocabBuilder {
+    /// Create a builder with no special tokens or pruning
+    pub fn new() -> Self {
+        VocabBuilder::default()
+    }
+
+    /// Reserve special tokens
+    ///
+    /// Special tokens always occupy the lowest ids, in the order given.
+    /// Repeated tokens are only reserved once.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - special tokens such as [`PAD`](crate::PAD)
+    pub fn special_tokens<S: AsRef<str>>(mut self, tokens: &[S]) -> Self {
+        for token in tokens {
+            self.reserve(token.as_ref());
+        }
+        self
+    }
+
+    /// Set the token used for out-of-vocabulary terms
+    ///
+    /// The token is reserved as a special token if it is not already.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - unknown token, usually [`UNK`](crate::UNK)
+    pub fn unk_token(mut self, token: &str) -> Self {
+        self.reserve(token);
+        self.unk = Some(token.to_owned());
+        self
+    }
+
+    /// Drop terms seen fewer than `min_freq` times
+    ///
+    /// Defaults to 1, which keeps every term.
+    pub fn min_freq(mut self, min_freq: u64) -> Self {
+        self.min_freq = min_freq;
+        self
+    }
+
+    /// Cap the number of vocabulary entries, special tokens included
+    ///
+    /// When the corpus has more terms than fit, the most frequent are
+    /// kept, breaking ties alphabetically.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Choose how ids are assigned to corpus terms
+    ///
+    /// Defaults to [`IdOrder::FirstSeen`].
+    pub fn order(mut self, order: IdOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Count input files with `threads` threads
+    ///
+    /// Defaults to 1. Passing 0 uses one thread per CPU core. The
+    /// vocabulary is identical whatever the number of threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Normalize the corpus with a custom chain of steps
+    ///
+    /// The built vocabulary normalizes text the same way when encoding,
+    /// and saves the normalizer with its terms. Defaults to
+    /// lowercasing only.
+    ///
+    /// # Arguments
+    ///
+    /// * `normalizer` - steps applied to raw text
+    pub fn normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Split the corpus with a custom tokenizer
+    ///
+    /// The built vocabulary encodes text with the same tokenizer.
+    /// Defaults to [`DefaultTokenizer`](crate::DefaultTokenizer).
+    ///
+    /// # Arguments
+    ///
+    /// * `tokenizer` - splits raw text into terms
+    pub fn tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = SharedTokenizer::new(tokenizer);
+        self
+    }
+
+    /// Count the pieces `model` splits each term into, instead of terms
+    ///
+    /// Use [`Model::Chars`] for a character vocabulary, or
+    /// [`Model::CharNgrams`] for fastText-style n-grams. Pruning and id
+    /// order apply to the pieces, and the built vocabulary encodes text
+    /// with the model. Defaults to [`Model::Word`]. WordPiece needs a
+    /// vocabulary to split terms, so building with it fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - splits each term into vocabulary entries
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Build the vocabulary from a raw text file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a raw text file to be parsed
+    pub fn build(&self, fpath: &str) -> Result<Vocab> {
+        self.build_files(&[fpath])
+    }
+
+    /// Build the vocabulary from any reader, such as stdin
+    ///
+    /// The input is streamed, so memory use is proportional to the
+    /// vocabulary rather than to the corpus.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - source of raw UTF-8 text
+    pub fn build_reader<R: Read>(&self, reader: R) -> Result<Vocab> {
+        let mut counts = TermCounts::default();
+        counts.add_reader(reader, Path::new(STREAM), &|text: &str| self.terms(text))?;
+
+        self.assign(counts)
+    }
+
+    /// Build one vocabulary from several raw text files
+    ///
+    /// Counts are merged across all files, which are read in the order
+    /// given, so first-seen ids follow that order. Each file is
+    /// streamed rather than read into memory.
+    ///
+    /// With more than one [thread](VocabBuilder::threads), files are
+    /// split into line-aligned byte ranges that are counted in parallel
+    /// and merged in order, giving the same vocabulary as one thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Paths to raw text files to be parsed
+    pub fn build_files<P: AsRef<Path>>(&self, paths: &[P]) -> Result<Vocab> {
+        let paths: Vec<&Path> = paths.iter().map(|path| path.as_ref()).collect();
+        let terms = |text: &str| self.terms(text);
+        let counts = if self.threads == 1 {
+            count::count_files(&paths, &terms)?
+        } else {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(self.threads)
+                .build()
+                .map_err(|e| Error::Threads { reason: e.to_string() })?;
+            count::count_files_parallel(&paths, &pool, count::MIN_SEGMENT, &terms)?
+        };
+
+        self.assign(counts)
+    }
+
+    /// Build one vocabulary from every file in a directory
+    ///
+    /// Files are read in lexicographic path order so ids are stable
+    /// across runs and platforms.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory containing raw text files
+    /// * `recursive` - Whether to descend into subdirectories
+    pub fn build_dir<P: AsRef<Path>>(&self, dir: P, recursive: bool) -> Result<Vocab> {
+        let mut paths = Vec::new();
+        list_files(dir.as_ref(), recursive, &mut paths)?;
+        paths.sort();
+
+        self.build_files(&paths)
+    }
+
+    /// Build one vocabulary from every file matching a glob pattern
+    ///
+    /// Files are read in lexicographic path order. A pattern that
+    /// matches nothing yields a vocabulary of only the special tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Glob such as `corpus/**/*.txt`
+    pub fn build_glob(&self, pattern: &str) -> Result<Vocab> {
+        let entries = glob::glob(pattern).map_err(|e| Error::Pattern {
+            pattern: pattern.to_owned(),
+            reason: e.msg.to_owned(),
+        })?;
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| Error::io(e.path().to_owned(), e.into()))?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        self.build_files(&paths)
+    }
+
+    /// Prune counted terms and assign ids after the special tokens
+    fn assign(&self, counts: TermCounts) -> Result<Vocab> {
+        let TermCounts { mut counts, seen } = self.split(counts)?;
+
+        let mut terms: Vec<String> = seen.into_iter()
+                                         .filter(|term| !self.specials.contains(term))
+                                         .filter(|term| counts[term] >= self.min_freq)
+                                         .collect();
+
+        if let Some(max_size) = self.max_size {
+            let budget = max_size.saturating_sub(self.specials.len());
+            if terms.len() > budget {
+                let mut kept = terms.clone();
+                sort_by_frequency(&mut kept, &counts);
+                kept.truncate(budget);
+                let kept: HashSet<String> = kept.into_iter().collect();
+                terms.retain(|term| kept.contains(term));
+            }
+        }
+
+        if self.order == IdOrder::Frequency {
+            sort_by_frequency(&mut terms, &counts);
+        }
+
+        let mut map = HashMap::new();
+        for (tok, term) in self.specials.iter().chain(&terms).enumerate() {
+            map.insert(term.to_owned(), tok as i32);
+        }
+        counts.retain(|term, _| map.contains_key(term));
+
+        let mut vocab = Vocab::from_parts(map, counts, self.specials.clone(), self.unk.as_deref())
+            .with_normalizer(self.normalizer.clone());
+        vocab.set_tokenizer(self.tokenizer.clone());
+
+        Ok(vocab.with_model(self.model.clone()))
+    }
+
+    /// Count the pieces of every term as often as the term was seen
+    ///
+    /// Pieces are first seen in the order of their terms.
+    fn split(&self, counts: TermCounts) -> Result<TermCounts> {
+        if self.model == Model::Word {
+            return Ok(counts);
+        }
+
+        let mut pieces = TermCounts::default();
+        for term in &counts.seen {
+            let split = self.model
+                            .split(term)
+                            .ok_or_else(|| config("WordPiece vocabularies are trained, not counted"))?;
+            for piece in split {
+                pieces.add_count(piece, counts.counts[term]);
+            }
+        }
+
+        Ok(pieces)
+    }
+
+    /// Normalize and split corpus text into terms
+    fn terms(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(&self.normalizer.normalize(text))
+    }
+
+    fn reserve(&mut self, token: &str) {
+        if !self.specials.iter().any(|s| s == token) {
+            self.specials.push(token.to_owned());
+        }
+    }
+}
+
+/// Collect the files under `dir`, descending into subdirectories if asked
+fn list_files(dir: &Path, recursive: bool, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| Error::io(dir, e))?.path();
+        let meta = fs::metadata(&path).map_err(|e| Error::io(&path, e))?;
+        if meta.is_dir() {
+            if recursive {
+                list_files(&path, recursive, paths)?;
+            }
+        } else {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sort terms by descending count, breaking ties alphabetically
+fn sort_by_frequency(terms: &mut [String], counts: &HashMap<String, u64>) {
+    terms.sort_by(|a, b| counts[b].cmp(&counts[a]).then_with(|| a.cmp(b)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{self, Write};
+
+    use crate::model::{CharNgrams, WordPiece};
+    use crate::tokenizer::RegexTokenizer;
+    use crate::vocab::{PAD, UNK};
+
+    const CORPUS: &str = "b a c a b a d e e";
+
+    fn build(builder: VocabBuilder) -> Vocab {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(CORPUS.as_bytes()).unwrap();
+        builder.build(file.path().to_str().unwrap()).unwrap()
+    }
+
+    fn ids(vocab: &Vocab, terms: &[&str]) -> Vec<Option<i32>> {
+        terms.iter().map(|term| vocab.token_to_id(term)).collect()
+    }
+
+    fn shards() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("b.txt"), "two two three").unwrap();
+        fs::write(dir.path().join("a.txt"), "one two").unwrap();
+        fs::write(dir.path().join("nested/c.txt"), "four one").unwrap();
+        fs::write(dir.path().join("nested/d.md"), "five").unwrap();
+        dir
+    }
+
+    #[test]
+    fn counts_terms() {
+        let vocab = build(VocabBuilder::new());
+        assert_eq!(vocab.frequency("a"), 3);
+        assert_eq!(vocab.frequency("d"), 1);
+        assert_eq!(vocab.frequency("z"), 0);
+        assert_eq!(vocab.most_common(3), vec![("a", 3), ("b", 2), ("e", 2)]);
+    }
+
+    #[test]
+    fn min_freq_drops_rare_terms() {
+        let vocab = build(VocabBuilder::new().min_freq(2));
+        assert_eq!(ids(&vocab, &["b", "a", "c", "e"]), vec![Some(0), Some(1), None, Some(2)]);
+        assert_eq!(vocab.frequency("c"), 0);
+    }
+
+    #[test]
+    fn max_size_keeps_most_frequent() {
+        let vocab = build(VocabBuilder::new().special_tokens(&[PAD]).max_size(3));
+        assert_eq!(vocab.size(), 3);
+        assert_eq!(ids(&vocab, &[PAD, "b", "a", "e"]), vec![Some(0), Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn frequency_order_breaks_ties_alphabetically() {
+        let vocab = build(VocabBuilder::new().unk_token(UNK).order(IdOrder::Frequency));
+        assert_eq!(ids(&vocab, &[UNK, "a", "b", "e", "c", "d"]),
+                   (0..6).map(Some).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn build_files_merges_counts_in_order() {
+        let dir = shards();
+        let paths = [dir.path().join("b.txt"), dir.path().join("a.txt")];
+        let vocab = VocabBuilder::new().build_files(&paths).unwrap();
+        assert_eq!(ids(&vocab, &["two", "three", "one"]), vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(vocab.frequency("two"), 3);
+    }
+
+    #[test]
+    fn threads_do_not_change_the_vocab() {
+        let dir = shards();
+        let sequential = VocabBuilder::new().build_dir(dir.path(), true).unwrap();
+        for threads in 0..4 {
+            let parallel = VocabBuilder::new().threads(threads).build_dir(dir.path(), true).unwrap();
+            assert_eq!(parallel, sequential);
+            assert_eq!(parallel.most_common(10), sequential.most_common(10));
+        }
+    }
+
+    #[test]
+    fn custom_tokenizer_builds_and_encodes() {
+        let upper = |text: &str| -> Vec<String> {
+            text.split_whitespace().map(str::to_uppercase).collect()
+        };
+        let dir = shards();
+        let vocab = VocabBuilder::new().tokenizer(upper)
+                                       .threads(2)
+                                       .build_dir(dir.path(), false)
+                                       .unwrap();
+        assert_eq!(ids(&vocab, &["ONE", "TWO", "one"]), vec![Some(0), Some(1), None]);
+        assert_eq!(vocab.encode("two three"), vec![1, 2]);
+        assert_eq!(vocab.clone().encode("two three"), vec![1, 2]);
+    }
+
+    #[test]
+    fn build_dir_sorts_paths() {
+        let dir = shards();
+        let flat = VocabBuilder::new().build_dir(dir.path(), false).unwrap();
+        assert_eq!(ids(&flat, &["one", "two", "three", "four"]),
+                   vec![Some(0), Some(1), Some(2), None]);
+
+        let deep = VocabBuilder::new().build_dir(dir.path(), true).unwrap();
+        assert_eq!(ids(&deep, &["four", "five"]), vec![Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn build_glob_matches_pattern() {
+        let dir = shards();
+        let pattern = format!("{}/**/*.txt", dir.path().display());
+        let vocab = VocabBuilder::new().build_glob(&pattern).unwrap();
+        assert_eq!(vocab.size(), 4);
+        assert_eq!(vocab.token_to_id("five"), None);
+    }
+
+    #[test]
+    fn build_glob_rejects_bad_pattern() {
+        assert!(matches!(VocabBuilder::new().build_glob("a/***"), Err(Error::Pattern { .. })));
+    }
+
+    /// Reader that hands out at most `step` bytes per call
+    struct Trickle<'a> {
+        bytes: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(buf.len()).min(self.bytes.len());
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn build_reader_matches_build() {
+        let text = "Café au lait, s'il vous plaît!\nCAFÉ\tnoir   café";
+        let whole = VocabBuilder::new().build_reader(text.as_bytes()).unwrap();
+        for step in 1..8 {
+            let trickle = Trickle { bytes: text.as_bytes(), step };
+            let streamed = VocabBuilder::new().build_reader(trickle).unwrap();
+            assert_eq!(streamed, whole);
+            assert_eq!(streamed.frequency("café"), 3);
+        }
+        assert_eq!(whole.decode(&whole.encode(text)), Vocab::tokenize(text.to_owned()));
+    }
+
+    #[test]
+    fn streaming_keeps_whitespace_attached_terms() {
+        let text = "a  b\n\n c\u{3000} d\t\te  \n";
+        let terms = RegexTokenizer::gpt2().tokenize(text);
+        let builder = VocabBuilder::new().normalizer(Normalizer::new())
+                                         .tokenizer(RegexTokenizer::gpt2());
+        for step in 1..8 {
+            let vocab = builder.build_reader(Trickle { bytes: text.as_bytes(), step }).unwrap();
+            let counted: u64 = vocab.entries().iter().map(|(term, _)| vocab.frequency(term)).sum();
+            assert_eq!(counted, terms.len() as u64);
+            assert_eq!(vocab.decode(&vocab.encode(text)), terms);
+        }
+    }
+
+    #[test]
+    fn build_reader_handles_terms_longer_than_a_chunk() {
+        let long = "x".repeat(count::CHUNK_SIZE * 2 + 3);
+        let text = format!("a {} b", long);
+        let vocab = VocabBuilder::new().build_reader(text.as_bytes()).unwrap();
+        assert_eq!(vocab.size(), 3);
+        assert_eq!(vocab.token_to_id(&long), Some(1));
+    }
+
+    #[test]
+    fn build_reader_rejects_invalid_utf8() {
+        let result = VocabBuilder::new().build_reader(&b"ok \xff\xfe"[..]);
+        match result {
+            Err(Error::Io { path, source }) => {
+                assert_eq!(path, Path::new(STREAM));
+                assert_eq!(source.kind(), io::ErrorKind::InvalidData);
+            }
+            other => panic!("expected Io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn counts_characters() {
+        let vocab = build(VocabBuilder::new().unk_token(UNK).model(Model::Chars));
+        assert_eq!(*vocab.model(), Model::Chars);
+        assert_eq!(vocab.size(), 6);
+        assert_eq!(vocab.frequency("a"), 3);
+        assert_eq!(vocab.encode("bad zed"), vec![1, 2, 4, 0, 5, 4]);
+    }
+
+    #[test]
+    fn counts_character_ngrams() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"where here there where").unwrap();
+        let path = file.path().to_str().unwrap();
+        let ngrams = CharNgrams::new(3, 4);
+        let vocab = VocabBuilder::new().unk_token(UNK)
+                                       .min_freq(2)
+                                       .model(Model::CharNgrams(ngrams))
+                                       .build(path)
+                                       .unwrap();
+        assert_eq!(vocab.frequency("<where>"), 2);
+        assert_eq!(vocab.frequency("ere>"), 4);
+        assert_eq!(vocab.frequency("her"), 4);
+        assert_eq!(vocab.token_to_id("<here>"), None);
+
+        let ids = vocab.encode("wherever");
+        let known: Vec<i32> = ngrams.ngrams("wherever")
+                                    .iter()
+                                    .filter_map(|gram| vocab.token_to_id(gram))
+                                    .collect();
+        assert_eq!(ids.iter().filter(|&&id| id != 0).copied().collect::<Vec<_>>(), known);
+        assert_eq!(ids[0], 0);
+        assert!(known.contains(&vocab.token_to_id("wher").unwrap()));
+
+        let parallel = VocabBuilder::new().unk_token(UNK)
+                                          .min_freq(2)
+                                          .model(Model::CharNgrams(ngrams))
+                                          .threads(2)
+                                          .build_files(&[path])
+                                          .unwrap();
+        assert_eq!(parallel, vocab);
+    }
+
+    #[test]
+    fn wordpiece_cannot_be_counted() {
+        let result = VocabBuilder::new().model(Model::WordPiece(WordPiece::new()))
+                                        .build_reader(CORPUS.as_bytes());
+        assert!(matches!(result, Err(Error::Config { .. })), "{:?}", result);
+    }
+}
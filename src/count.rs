@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::error::{Error, Result};
+use crate::tokenizer::Tokenizer;
+
+/// Number of bytes requested per read while counting a stream
+pub(crate) const CHUNK_SIZE: usize = 1 << 16;
+
+/// Smallest byte range worth counting on its own thread
+pub(crate) const MIN_SEGMENT: u64 = 1 << 20;
+
+/// Path reported in errors for streams that have none
+pub(crate) const STREAM: &str = "<stream>";
+
+/// Term counts gathered from a corpus
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct TermCounts {
+    /// Number of times each term was seen
+    pub(crate) counts: HashMap<String, u64>,
+    /// Distinct terms in the order they were first seen
+    pub(crate) seen: Vec<String>,
+}
+
+impl TermCounts {
+    pub(crate) fn add(&mut self, term: String) {
+        self.add_count(term, 1);
+    }
+
+    /// Record `count` more sightings of a term
+    pub(crate) fn add_count(&mut self, term: String, count: u64) {
+        match self.counts.get_mut(&term) {
+            Some(total) => *total += count,
+            None => {
+                self.seen.push(term.clone());
+                self.counts.insert(term, count);
+            }
+        }
+    }
+
+    /// Fold in the counts of text that came after ours
+    ///
+    /// Merging partial counts in corpus order keeps first-seen order
+    /// identical to counting the whole corpus in one pass.
+    pub(crate) fn merge(&mut self, other: TermCounts) {
+        let TermCounts { mut counts, seen } = other;
+        for term in seen {
+            let count = counts.remove(&term).unwrap_or(0);
+            self.add_count(term, count);
+        }
+    }
+
+    /// Count the terms of a stream chunk by chunk
+    ///
+    /// Chunks are cut at their last [cut point](is_cut), which can
+    /// neither split a term nor fall inside a multi-byte UTF-8 sequence.
+    /// The tail is carried into the next chunk, so only a run of text
+    /// longer than the chunk without a cut point makes the buffer grow.
+    pub(crate) fn add_reader<R: Read>(&mut self,
+                                      mut reader: R,
+                                      path: &Path,
+                                      tokenizer: &dyn Tokenizer) -> Result<()> {
+        let mut buf = Vec::with_capacity(CHUNK_SIZE);
+        loop {
+            let filled = buf.len();
+            buf.resize(filled + CHUNK_SIZE, 0);
+            let read = match reader.read(&mut buf[filled..]) {
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    buf.truncate(filled);
+                    continue;
+                }
+                Err(e) => return Err(Error::io(path, e)),
+            };
+            buf.truncate(filled + read);
+
+            if read == 0 {
+                return self.add_chunk(&buf, path, tokenizer);
+            }
+            if let Some(end) = (filled.max(1)..buf.len()).rev().find(|&at| is_cut(&buf, at)) {
+                self.add_chunk(&buf[..end], path, tokenizer)?;
+                buf.drain(..end);
+            }
+        }
+    }
+
+    /// Count the terms of a chunk of UTF-8 text
+    fn add_chunk(&mut self, bytes: &[u8], path: &Path, tokenizer: &dyn Tokenizer) -> Result<()> {
+        let text = std::str::from_utf8(bytes).map_err(|e| {
+            Error::io(path, io::Error::new(io::ErrorKind::InvalidData, e))
+        })?;
+        for term in tokenizer.tokenize(text) {
+            self.add(term);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether text may be cut in two just before `bytes[at]`
+///
+/// Cut points are ASCII whitespace bytes that follow a character other
+/// than whitespace. Tokenizers never join such a pair into one term,
+/// even those that attach leading whitespace to words, so counting the
+/// two sides apart gives the same terms as counting them together.
+fn is_cut(bytes: &[u8], at: usize) -> bool {
+    if at == 0 || at >= bytes.len() || !bytes[at].is_ascii_whitespace() {
+        return false;
+    }
+    let start = (at.saturating_sub(4)..at).rev()
+                                          .find(|&idx| bytes[idx] & 0xc0 != 0x80)
+                                          .unwrap_or(at - 1);
+
+    // Invalid UTF-8 is reported by the chunk it ends up in.
+    std::str::from_utf8(&bytes[start..at])
+        .map_or(true, |prev| !prev.chars().next_back().is_some_and(char::is_whitespace))
+}
+
+/// A byte range of a file, starting and ending at cut points
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Segment {
+    path: PathBuf,
+    start: u64,
+    end: u64,
+}
+
+impl Segment {
+    fn count(&self, tokenizer: &dyn Tokenizer) -> Result<TermCounts> {
+        let mut file = File::open(&self.path).map_err(|e| Error::io(&self.path, e))?;
+        file.seek(SeekFrom::Start(self.start)).map_err(|e| Error::io(&self.path, e))?;
+
+        let mut counts = TermCounts::default();
+        counts.add_reader(file.take(self.end - self.start), &self.path, tokenizer)?;
+
+        Ok(counts)
+    }
+}
+
+/// Count files one after another on the current thread
+pub(crate) fn count_files(paths: &[&Path], tokenizer: &dyn Tokenizer) -> Result<TermCounts> {
+    let mut counts = TermCounts::default();
+    for &path in paths {
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        counts.add_reader(file, path, tokenizer)?;
+    }
+
+    Ok(counts)
+}
+
+/// Count files on a thread pool and merge the partial counts in order
+///
+/// Each file is split into up to one segment per thread, none shorter
+/// than `min_len` bytes unless the file itself is.
+pub(crate) fn count_files_parallel(paths: &[&Path],
+                                   pool: &ThreadPool,
+                                   min_len: u64,
+                                   tokenizer: &dyn Tokenizer) -> Result<TermCounts> {
+    let parts = pool.current_num_threads();
+    let mut segments = Vec::new();
+    for &path in paths {
+        segments.extend(split_file(path, parts, min_len)?);
+    }
+
+    let partials: Vec<TermCounts> = pool.install(|| {
+        segments.par_iter()
+                .map(|segment| segment.count(tokenizer))
+                .collect::<Result<_>>()
+    })?;
+
+    let mut counts = TermCounts::default();
+    for partial in partials {
+        counts.merge(partial);
+    }
+
+    Ok(counts)
+}
+
+/// Split a file into at most `parts` segments that meet at cut points
+fn split_file(path: &Path, parts: usize, min_len: u64) -> Result<Vec<Segment>> {
+    let mut file = File::open(path).map_err(|e| Error::io(path, e))?;
+    let len = file.metadata().map_err(|e| Error::io(path, e))?.len();
+    let parts = (parts as u64).min(len / min_len.max(1)).max(1);
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for part in 1..=parts {
+        let end = if part == parts {
+            len
+        } else {
+            next_cut(&mut file, (len * part / parts).max(start + 1), len)
+                .map_err(|e| Error::io(path, e))?
+        };
+        if end > start {
+            segments.push(Segment { path: path.to_owned(), start, end });
+            start = end;
+        }
+    }
+    if segments.is_empty() {
+        segments.push(Segment { path: path.to_owned(), start: 0, end: len });
+    }
+
+    Ok(segments)
+}
+
+/// Find the first cut point at or after `from`, or `len` if there is none
+fn next_cut(file: &mut File, from: u64, len: u64) -> io::Result<u64> {
+    // A few bytes before the next offset to examine are kept, so the
+    // character in front of it can be decoded.
+    let mut pos = from.saturating_sub(4);
+    let mut next = from;
+    let mut buf = Vec::new();
+    let mut block = [0; 4096];
+    file.seek(SeekFrom::Start(pos))?;
+    loop {
+        let read = file.read(&mut block)?;
+        if read == 0 {
+            return Ok(len);
+        }
+        buf.extend_from_slice(&block[..read]);
+        let skip = (next - pos) as usize;
+        if let Some(at) = (skip..buf.len()).find(|&at| is_cut(&buf, at)) {
+            return Ok(pos + at as u64);
+        }
+        next = pos + buf.len() as u64;
+        let drop = buf.len().saturating_sub(4);
+        buf.drain(..drop);
+        pos += drop as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::tokenizer::{DefaultTokenizer, RegexTokenizer};
+
+    const TEXT: &str = "the cat sat\non the mat\n\nthe end\nof the story\nfin";
+
+    fn pool(threads: usize) -> ThreadPool {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap()
+    }
+
+    #[test]
+    fn merge_keeps_first_seen_order() {
+        let mut left = TermCounts::default();
+        left.add_reader("b a b".as_bytes(), Path::new(STREAM), &DefaultTokenizer).unwrap();
+        let mut right = TermCounts::default();
+        right.add_reader("c a d".as_bytes(), Path::new(STREAM), &DefaultTokenizer).unwrap();
+        left.merge(right);
+
+        let mut whole = TermCounts::default();
+        whole.add_reader("b a b c a d".as_bytes(), Path::new(STREAM), &DefaultTokenizer).unwrap();
+        assert_eq!(left, whole);
+    }
+
+    #[test]
+    fn segments_end_at_cut_points() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.txt");
+        fs::write(&path, TEXT).unwrap();
+
+        let segments = split_file(&path, 4, 1).unwrap();
+        assert!(segments.len() > 1);
+        assert_eq!(segments[0].start, 0);
+        assert_eq!(segments.last().unwrap().end, TEXT.len() as u64);
+        for pair in segments.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert!(is_cut(TEXT.as_bytes(), pair[0].end as usize));
+        }
+    }
+
+    #[test]
+    fn cut_points_follow_non_whitespace() {
+        let text = "a  b\u{3000} c\u{e9}\td\n";
+        let cuts: Vec<usize> = (0..text.len()).filter(|&at| is_cut(text.as_bytes(), at)).collect();
+        assert_eq!(cuts, vec![1, 11, 13]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.txt");
+        // The ideographic space straddles the first 4096-byte read.
+        let text = format!("{}\u{3000} x y", "y".repeat(4095));
+        fs::write(&path, &text).unwrap();
+        let mut file = File::open(&path).unwrap();
+        let len = text.len() as u64;
+        assert_eq!(next_cut(&mut file, 0, len).unwrap(), 4100);
+        assert_eq!(next_cut(&mut file, 4098, len).unwrap(), 4100);
+        assert_eq!(next_cut(&mut file, 4101, len).unwrap(), len);
+    }
+
+    #[test]
+    fn small_files_are_not_split() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.txt");
+        fs::write(&path, TEXT).unwrap();
+        assert_eq!(split_file(&path, 4, MIN_SEGMENT).unwrap().len(), 1);
+
+        let empty = dir.path().join("empty.txt");
+        fs::write(&empty, "").unwrap();
+        assert_eq!(split_file(&empty, 4, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parallel_counts_match_sequential() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.txt");
+        let second = dir.path().join("second.txt");
+        fs::write(&first, TEXT).unwrap();
+        fs::write(&second, "fin de l'histoire\nthe end").unwrap();
+        let paths = [first.as_path(), second.as_path()];
+
+        let sequential = count_files(&paths, &DefaultTokenizer).unwrap();
+        for threads in 1..6 {
+            let parallel = count_files_parallel(&paths, &pool(threads), 1, &DefaultTokenizer).unwrap();
+            assert_eq!(parallel, sequential);
+        }
+    }
+
+    #[test]
+    fn parallel_counts_keep_whitespace_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.txt");
+        fs::write(&path, "x\n  y  z\n\n\n w \u{3000} v\t\t\n".repeat(7)).unwrap();
+        let paths = [path.as_path()];
+        let gpt2 = RegexTokenizer::gpt2();
+
+        let sequential = count_files(&paths, &gpt2).unwrap();
+        for threads in 2..9 {
+            let parallel = count_files_parallel(&paths, &pool(threads), 1, &gpt2).unwrap();
+            assert_eq!(parallel, sequential);
+        }
+    }
+}
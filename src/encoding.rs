@@ -0,0 +1,980 @@
+//! Encodings that remember where each token came from
+//!
+//! Text is normalized in groups of characters so every normalized byte
+//! knows the raw bytes behind it, the tokenizer reports the span of
+//! each term, and the model the span of each piece within its term.
+//! Chaining the three maps every token back to the raw text.
+
+use std::iter;
+use std::ops::Range;
+
+use crate::error::{Error, Result};
+use crate::model::Model;
+use crate::normalizer::Normalizer;
+use crate::template::{Item, Template};
+use crate::tokenizer::Tokenizer;
+use crate::vocab::PAD;
+
+/// Token ids of a text, with what a model needs alongside them
+///
+/// Besides the ids, an encoding holds the vocabulary entry of each
+/// token, where it came from, and the masks and segment ids models
+/// take as input, so none of them need rebuilding from the ids.
+///
+/// Offsets index the raw text given to
+/// [`Vocab::encode_with_offsets`](crate::Vocab::encode_with_offsets),
+/// before normalization, as `(start, end)` pairs. A token that
+/// normalization made from several characters, such as `fi` from `ﬁ`,
+/// covers all of them. The tokens of a pair index the text they came
+/// from, see [`Encoding::sequence_ids`].
+///
+/// ```
+/// use tok::Vocab;
+///
+/// let vocab = Vocab::from_reader("hello world".as_bytes())?;
+/// let encoding = vocab.encode_with_offsets("Héllo, HELLO World")?;
+/// assert_eq!(encoding.ids(), &[0, 1]);
+/// assert_eq!(encoding.offsets(), &[(8, 13), (14, 19)]);
+/// assert_eq!(encoding.char_offsets(), &[(7, 12), (13, 18)]);
+/// assert_eq!(encoding.word_ids(), &[Some(1), Some(2)]);
+/// assert_eq!(encoding.attention_mask(), &[1, 1]);
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Encoding {
+    ids: Vec<i32>,
+    /// Vocabulary entry of each id
+    tokens: Vec<String>,
+    /// Byte span of the raw text behind each token
+    offsets: Vec<(usize, usize)>,
+    /// Character span of the raw text behind each token
+    char_offsets: Vec<(usize, usize)>,
+    /// Index of the term each token was split from
+    words: Vec<Option<usize>>,
+    /// Text of a pair each token came from
+    sequences: Vec<Option<usize>>,
+    /// Segment of each token
+    type_ids: Vec<u32>,
+    /// 1 for tokens a model should attend to
+    attention_mask: Vec<u32>,
+    /// 1 for special tokens
+    special_tokens_mask: Vec<u32>,
+    /// Windows of the tokens cut off by truncation
+    overflowing: Vec<Encoding>,
+}
+
+impl Encoding {
+    /// Get the token ids
+    pub fn ids(&self) -> &[i32] {
+        &self.ids
+    }
+
+    /// Get the vocabulary entry of each token
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    /// Get the byte span of the raw text behind each token
+    pub fn offsets(&self) -> &[(usize, usize)] {
+        &self.offsets
+    }
+
+    /// Get the character span of the raw text behind each token
+    ///
+    /// A token that starts or ends inside a character, as byte-level
+    /// pieces can, covers the whole character.
+    pub fn char_offsets(&self) -> &[(usize, usize)] {
+        &self.char_offsets
+    }
+
+    /// Get the index of the tokenizer term each token was split from
+    ///
+    /// Terms are counted whether or not any of their pieces are in the
+    /// vocabulary, so indices skip terms that were dropped as unknown.
+    pub fn word_ids(&self) -> &[Option<usize>] {
+        &self.words
+    }
+
+    /// Get which text each token came from, 0 or 1 for the texts of a pair
+    ///
+    /// Tokens inserted by a [`Template`] and padding come from neither.
+    pub fn sequence_ids(&self) -> &[Option<usize>] {
+        &self.sequences
+    }
+
+    /// Get the segment of each token
+    ///
+    /// Every token of a single text is in segment 0, and those of the
+    /// second text of a pair in segment 1, unless a [`Template`] sets
+    /// others.
+    pub fn type_ids(&self) -> &[u32] {
+        &self.type_ids
+    }
+
+    /// Get 1 for every token a model should attend to and 0 for padding
+    pub fn attention_mask(&self) -> &[u32] {
+        &self.attention_mask
+    }
+
+    /// Get 1 for every special token and 0 for the rest
+    ///
+    /// The unknown token stands for text, so is not marked.
+    pub fn special_tokens_mask(&self) -> &[u32] {
+        &self.special_tokens_mask
+    }
+
+    /// Get the windows of tokens that truncation cut off, in order
+    ///
+    /// See [`Encoding::truncate`].
+    pub fn overflowing(&self) -> &[Encoding] {
+        &self.overflowing
+    }
+
+    /// Get the number of tokens
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Check whether there are no tokens
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Find the first token covering a character of the raw text
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - character index in the raw text
+    pub fn char_to_token(&self, pos: usize) -> Option<usize> {
+        self.char_offsets.iter().position(|&(start, end)| start <= pos && pos < end)
+    }
+
+    /// Get the range of tokens split from a tokenizer term
+    ///
+    /// Returns `(first, last + 1)`, or `None` if no token came from
+    /// the term.
+    ///
+    /// # Arguments
+    ///
+    /// * `word` - index of the term, as in [`Encoding::word_ids`]
+    pub fn word_to_tokens(&self, word: usize) -> Option<(usize, usize)> {
+        let first = self.words.iter().position(|&id| id == Some(word))?;
+        let last = self.words.iter().rposition(|&id| id == Some(word))?;
+
+        Some((first, last + 1))
+    }
+
+    /// Keep the first `max_length` tokens, moving the rest to [`Encoding::overflowing`]
+    ///
+    /// The tokens cut off are split into windows of up to `max_length`
+    /// tokens, each starting with the last `stride` tokens of the one
+    /// before, so every token is seen with some context. A stride of
+    /// `max_length` or more is taken as `max_length - 1`. A `max_length`
+    /// of 0 keeps no tokens and moves them all to a single window.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_length` - most tokens to keep
+    /// * `stride` - tokens each overflowing window repeats from the last
+    pub fn truncate(&mut self, max_length: usize, stride: usize) {
+        if self.len() <= max_length {
+            return;
+        }
+        if max_length == 0 {
+            let all = self.slice(0..self.len());
+            *self = Encoding { overflowing: vec![all], ..Encoding::default() };
+            return;
+        }
+
+        let step = max_length - stride.min(max_length - 1);
+        let mut overflowing = Vec::new();
+        let mut start = step;
+        loop {
+            let end = (start + max_length).min(self.len());
+            overflowing.push(self.slice(start..end));
+            if end == self.len() {
+                break;
+            }
+            start += step;
+        }
+        *self = self.slice(0..max_length);
+        self.overflowing = overflowing;
+    }
+
+    /// Pad the tokens, and those of every overflowing window, to `length`
+    ///
+    /// Padding takes `pad_id` with no offsets or word, type id 0, and
+    /// is masked out of attention. Encodings already as long are left
+    /// as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - number of tokens to pad to
+    /// * `pad_id` - id of the padding token
+    /// * `pad_token` - entry of the padding token
+    /// * `side` - whether padding goes before or after the tokens
+    pub fn pad(&mut self, length: usize, pad_id: i32, pad_token: &str, side: PaddingSide) {
+        for window in &mut self.overflowing {
+            window.pad(length, pad_id, pad_token, side);
+        }
+        let count = length.saturating_sub(self.len());
+        if count == 0 {
+            return;
+        }
+
+        let at = match side {
+            PaddingSide::Left => 0,
+            PaddingSide::Right => self.len(),
+        };
+        fill(&mut self.ids, at, count, pad_id);
+        fill(&mut self.tokens, at, count, pad_token.to_owned());
+        fill(&mut self.offsets, at, count, (0, 0));
+        fill(&mut self.char_offsets, at, count, (0, 0));
+        fill(&mut self.words, at, count, None);
+        fill(&mut self.sequences, at, count, None);
+        fill(&mut self.type_ids, at, count, 0);
+        fill(&mut self.attention_mask, at, count, 0);
+        fill(&mut self.special_tokens_mask, at, count, 1);
+    }
+
+    /// Copy a range of tokens, without overflowing windows
+    fn slice(&self, range: Range<usize>) -> Encoding {
+        Encoding {
+            ids: self.ids[range.clone()].to_vec(),
+            tokens: self.tokens[range.clone()].to_vec(),
+            offsets: self.offsets[range.clone()].to_vec(),
+            char_offsets: self.char_offsets[range.clone()].to_vec(),
+            words: self.words[range.clone()].to_vec(),
+            sequences: self.sequences[range.clone()].to_vec(),
+            type_ids: self.type_ids[range.clone()].to_vec(),
+            attention_mask: self.attention_mask[range.clone()].to_vec(),
+            special_tokens_mask: self.special_tokens_mask[range].to_vec(),
+            overflowing: Vec::new(),
+        }
+    }
+
+    /// Get the number of tokens, counting every overflowing window
+    fn longest(&self) -> usize {
+        self.overflowing.iter().map(Encoding::len).fold(self.len(), usize::max)
+    }
+
+    fn push(&mut self,
+            id: i32,
+            token: &str,
+            special: bool,
+            offsets: (usize, usize),
+            char_offsets: (usize, usize),
+            word: usize) {
+        self.ids.push(id);
+        self.tokens.push(token.to_owned());
+        self.offsets.push(offsets);
+        self.char_offsets.push(char_offsets);
+        self.words.push(Some(word));
+        self.sequences.push(Some(0));
+        self.type_ids.push(0);
+        self.attention_mask.push(1);
+        self.special_tokens_mask.push(special as u32);
+    }
+
+    /// Append a token inserted by a template
+    fn push_special(&mut self, id: i32, token: &str, type_id: u32) {
+        self.ids.push(id);
+        self.tokens.push(token.to_owned());
+        self.offsets.push((0, 0));
+        self.char_offsets.push((0, 0));
+        self.words.push(None);
+        self.sequences.push(None);
+        self.type_ids.push(type_id);
+        self.attention_mask.push(1);
+        self.special_tokens_mask.push(1);
+    }
+
+    /// Append the tokens of text `sequence` of a pair, in segment `type_id`
+    fn append(&mut self, other: &Encoding, sequence: usize, type_id: u32) {
+        self.ids.extend_from_slice(&other.ids);
+        self.tokens.extend_from_slice(&other.tokens);
+        self.offsets.extend_from_slice(&other.offsets);
+        self.char_offsets.extend_from_slice(&other.char_offsets);
+        self.words.extend_from_slice(&other.words);
+        self.sequences.extend(iter::repeat_n(Some(sequence), other.len()));
+        self.type_ids.extend(iter::repeat_n(type_id, other.len()));
+        self.attention_mask.extend_from_slice(&other.attention_mask);
+        self.special_tokens_mask.extend_from_slice(&other.special_tokens_mask);
+    }
+}
+
+/// Insert `count` copies of `value` at `at`
+fn fill<T: Clone>(values: &mut Vec<T>, at: usize, count: usize, value: T) {
+    values.splice(at..at, iter::repeat_n(value, count));
+}
+
+/// Which end of a sequence padding is added to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingSide {
+    /// Before the tokens
+    Left,
+    /// After the tokens
+    #[default]
+    Right,
+}
+
+/// The length a batch is padded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingLength {
+    /// The longest encoding of the batch
+    #[default]
+    Longest,
+    /// A fixed number of tokens; longer encodings are left as they are
+    Fixed(usize),
+}
+
+/// How [`Vocab::encode_batch`](crate::Vocab::encode_batch) pads encodings to one length
+///
+/// ```
+/// use tok::{Padding, PaddingSide, Vocab, PAD};
+///
+/// let vocab = Vocab::builder().special_tokens(&[PAD])
+///                             .build_reader("a b c".as_bytes())?
+///                             .with_padding(Padding::longest().with_multiple_of(4).with_side(PaddingSide::Left));
+/// let batch = vocab.encode_batch(&["a b c b a", "c"])?;
+/// assert_eq!(batch[1].ids(), &[0, 0, 0, 0, 0, 0, 0, 3]);
+/// assert_eq!(batch[1].attention_mask(), &[0, 0, 0, 0, 0, 0, 0, 1]);
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Padding {
+    length: PaddingLength,
+    /// Lengths are rounded up to a multiple of this
+    multiple_of: Option<usize>,
+    side: PaddingSide,
+    /// Entry whose id pads
+    token: String,
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding::longest()
+    }
+}
+
+impl Padding {
+    /// Pad to the longest encoding of each batch
+    pub fn longest() -> Self {
+        Padding::new(PaddingLength::Longest)
+    }
+
+    /// Pad every encoding to `length` tokens
+    pub fn fixed(length: usize) -> Self {
+        Padding::new(PaddingLength::Fixed(length))
+    }
+
+    /// Pad to `length` after the tokens, with [`PAD`]
+    pub fn new(length: PaddingLength) -> Self {
+        Padding { length, multiple_of: None, side: PaddingSide::Right, token: PAD.to_owned() }
+    }
+
+    /// Round the padded length up to a multiple of `multiple_of`
+    ///
+    /// Hardware often runs faster on lengths that are multiples of 8.
+    pub fn with_multiple_of(mut self, multiple_of: usize) -> Self {
+        self.multiple_of = Some(multiple_of).filter(|&n| n > 0);
+        self
+    }
+
+    /// Add padding before or after the tokens
+    pub fn with_side(mut self, side: PaddingSide) -> Self {
+        self.side = side;
+        self
+    }
+
+    /// Pad with the id of `token` instead of [`PAD`]
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = token.to_owned();
+        self
+    }
+
+    /// Get the length padded to
+    pub fn length(&self) -> PaddingLength {
+        self.length
+    }
+
+    /// Get the multiple padded lengths are rounded up to, if any
+    pub fn multiple_of(&self) -> Option<usize> {
+        self.multiple_of
+    }
+
+    /// Get the side padding is added to
+    pub fn side(&self) -> PaddingSide {
+        self.side
+    }
+
+    /// Get the entry whose id pads
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Pad a batch of encodings with the id of the padding token
+    pub(crate) fn apply(&self, encodings: &mut [Encoding], pad_id: i32) {
+        let length = match self.length {
+            PaddingLength::Longest => encodings.iter().map(Encoding::longest).max().unwrap_or(0),
+            PaddingLength::Fixed(length) => length,
+        };
+        let length = match self.multiple_of {
+            Some(n) => length.div_ceil(n) * n,
+            None => length,
+        };
+        for encoding in encodings {
+            encoding.pad(length, pad_id, &self.token, self.side);
+        }
+    }
+}
+
+/// Which text of a pair truncation cuts
+///
+/// A single text is the only one to cut, so every strategy cuts it.
+/// Encoding a pair fails when the text that is not cut leaves no room
+/// for a single token of the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    /// Cut the longer text, one token at a time
+    #[default]
+    LongestFirst,
+    /// Cut only the first text
+    OnlyFirst,
+    /// Cut only the second text
+    OnlySecond,
+}
+
+/// How encodings are cut down to a maximum length
+///
+/// The tokens cut off are kept as overflowing windows, see
+/// [`Encoding::truncate`].
+///
+/// ```
+/// use tok::{Truncation, Vocab};
+///
+/// let vocab = Vocab::from_reader("a b c d e".as_bytes())?.with_truncation(Truncation::new(2).with_stride(1));
+/// let encoding = vocab.encode_with_offsets("a b c d")?;
+/// assert_eq!(encoding.ids(), &[0, 1]);
+/// let windows: Vec<&[i32]> = encoding.overflowing().iter().map(|window| window.ids()).collect();
+/// assert_eq!(windows, vec![&[1, 2][..], &[2, 3][..]]);
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncation {
+    max_length: usize,
+    stride: usize,
+    strategy: TruncationStrategy,
+}
+
+impl Truncation {
+    /// Keep at most `max_length` tokens, cutting the longest text first
+    pub fn new(max_length: usize) -> Self {
+        Truncation { max_length, stride: 0, strategy: TruncationStrategy::LongestFirst }
+    }
+
+    /// Repeat the last `stride` tokens of each window at the start of the next
+    ///
+    /// Defaults to 0.
+    pub fn with_stride(mut self, stride: usize) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    /// Choose which text of a pair is cut
+    pub fn with_strategy(mut self, strategy: TruncationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Get the most tokens kept
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Get the tokens each overflowing window repeats
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Get which text of a pair is cut
+    pub fn strategy(&self) -> TruncationStrategy {
+        self.strategy
+    }
+
+    /// Cut the encodings of a text or a pair, leaving room for `added` inserted tokens
+    ///
+    /// Fails if the inserted tokens alone take up the maximum length, or
+    /// if the strategy would have to cut a text of the pair entirely.
+    pub(crate) fn apply(&self, first: &mut Encoding, second: Option<&mut Encoding>, added: usize) -> Result<()> {
+        if self.max_length <= added {
+            return Err(Error::Config {
+                reason: format!("max length {} leaves no room beside the {} tokens of the template", self.max_length, added),
+            });
+        }
+        let budget = self.max_length - added;
+        let second = match second {
+            Some(second) => second,
+            None => {
+                first.truncate(budget, self.stride);
+                return Ok(());
+            }
+        };
+        let (a, b) = (first.len(), second.len());
+        if a + b <= budget {
+            return Ok(());
+        }
+
+        let (cut_a, cut_b) = match self.strategy {
+            TruncationStrategy::LongestFirst => {
+                // Cut the longer text down to the shorter, then both in turn.
+                let mut excess = a + b - budget;
+                let cut = excess.min(a.abs_diff(b));
+                excess -= cut;
+                let (cut_a, cut_b) = if a > b { (a - cut, b) } else { (a, b - cut) };
+                (cut_a - excess.div_ceil(2), cut_b - excess / 2)
+            }
+            TruncationStrategy::OnlyFirst => (budget.saturating_sub(b), b),
+            TruncationStrategy::OnlySecond => (a, budget.saturating_sub(a)),
+        };
+        // No strategy may empty a text or leave the pair too long.
+        if (cut_a == 0 && a > 0) || (cut_b == 0 && b > 0) || cut_a + cut_b > budget {
+            return Err(Error::Truncation {
+                reason: format!("no room is left for both texts of the pair within {} tokens", budget),
+            });
+        }
+        first.truncate(cut_a, self.stride);
+        second.truncate(cut_b, self.stride);
+
+        Ok(())
+    }
+}
+
+/// A vocabulary that encodes text, owned or mapped
+///
+/// Methods mirror those of [`Vocab`](crate::Vocab).
+pub(crate) trait Encoder {
+    fn normalizer(&self) -> &Normalizer;
+
+    fn tokenizer(&self) -> &dyn Tokenizer;
+
+    fn model(&self) -> &Model;
+
+    fn unk_id(&self) -> Option<i32>;
+
+    fn token_to_id(&self, term: &str) -> Option<i32>;
+
+    fn id_to_token(&self, id: i32) -> Option<&str>;
+
+    fn is_special(&self, term: &str) -> bool;
+
+    fn template(&self) -> Option<&Template>;
+
+    fn truncation(&self) -> Option<&Truncation>;
+}
+
+/// Encode a text or a pair, truncated and placed in the vocabulary's template
+pub(crate) fn encode<E: Encoder>(vocab: &E, first: &str, second: Option<&str>) -> Result<Encoding> {
+    let mut first = encode_text(vocab, first);
+    let mut second = second.map(|text| encode_text(vocab, text));
+    if let Some(truncation) = vocab.truncation() {
+        let added = vocab.template().map_or(0, |template| template.added_tokens(second.is_some()));
+        truncation.apply(&mut first, second.as_mut(), added)?;
+    }
+    if vocab.template().is_none() && second.is_none() {
+        return Ok(first);
+    }
+
+    let lookup = |token: &str| vocab.token_to_id(token);
+    let mut overflowing: Vec<Encoding> = first.overflowing
+                                              .iter()
+                                              .map(|window| join(window, second.as_ref(), vocab.template(), lookup))
+                                              .collect();
+    if let Some(second) = &second {
+        overflowing.extend(second.overflowing
+                                 .iter()
+                                 .map(|window| join(&first, Some(window), vocab.template(), lookup)));
+    }
+    let mut joined = join(&first, second.as_ref(), vocab.template(), lookup);
+    joined.overflowing = overflowing;
+
+    Ok(joined)
+}
+
+/// Place the tokens of a text or a pair in a template, without overflowing windows
+///
+/// Without a template, the texts follow each other in segments 0 and 1.
+fn join<L>(first: &Encoding, second: Option<&Encoding>, template: Option<&Template>, lookup: L) -> Encoding
+where
+    L: Fn(&str) -> Option<i32>,
+{
+    let mut joined = Encoding::default();
+    let template = match template {
+        Some(template) => template,
+        None => {
+            joined.append(first, 0, 0);
+            if let Some(second) = second {
+                joined.append(second, 1, 1);
+            }
+            return joined;
+        }
+    };
+
+    for item in template.items(second.is_some()) {
+        match item {
+            Item::Sequence { index: 0, type_id } => joined.append(first, 0, *type_id),
+            Item::Sequence { index, type_id } => {
+                if let Some(second) = second {
+                    joined.append(second, *index, *type_id);
+                }
+            }
+            Item::Special { token, type_id } => {
+                if let Some(id) = lookup(token) {
+                    joined.push_special(id, token, *type_id);
+                }
+            }
+        }
+    }
+
+    joined
+}
+
+/// Encode raw text, following every token back to its span
+///
+/// Ids are found as in [`Model::encode_term`].
+fn encode_text<E: Encoder>(vocab: &E, text: &str) -> Encoding {
+    let (normalized, spans) = vocab.normalizer().normalize_aligned(text);
+    let chars: Vec<usize> = text.char_indices().map(|(at, _)| at).collect();
+    let lookup = |piece: &str| vocab.token_to_id(piece);
+    let unk = vocab.unk_id();
+
+    let mut encoding = Encoding::default();
+    for (word, (term, (start, end))) in vocab.tokenizer().tokenize_with_offsets(&normalized).into_iter().enumerate() {
+        // Terms the tokenizer rewrote can only be placed as a whole.
+        let exact = normalized.get(start..end) == Some(term.as_str());
+        vocab.model().encode_spans(&term, lookup, unk, |id, (from, to)| {
+            let (from, to) = if exact { (start + from, start + to) } else { (start, end) };
+            let offsets = raw_span(&spans, from, to, text.len());
+            let char_offsets = char_span(&chars, offsets);
+            let token = vocab.id_to_token(id).unwrap_or_default();
+            let special = Some(id) != unk && vocab.is_special(token);
+            encoding.push(id, token, special, offsets, char_offsets, word);
+        });
+    }
+
+    encoding
+}
+
+/// Get the raw byte span behind normalized bytes `from..to`
+fn raw_span(spans: &[(usize, usize)], from: usize, to: usize, len: usize) -> (usize, usize) {
+    if from < to {
+        return (spans[from].0, spans[to - 1].1);
+    }
+    let at = spans.get(from).map_or(len, |&(start, _)| start);
+
+    (at, at)
+}
+
+/// Get the character span covering a byte span, given where characters start
+fn char_span(chars: &[usize], (start, end): (usize, usize)) -> (usize, usize) {
+    let ceil = |at: usize| chars.partition_point(|&c| c < at);
+    if start == end {
+        return (ceil(start), ceil(end));
+    }
+
+    (chars.partition_point(|&c| c <= start) - 1, ceil(end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::Error;
+    use crate::mapped::MappedVocab;
+    use crate::model::{Bpe, BpeTrainer, CharNgrams};
+    use crate::normalizer::UnicodeForm;
+    use crate::tokenizer::{RegexTokenizer, WhitespaceTokenizer};
+    use crate::vocab::{Vocab, MASK, UNK};
+
+    fn spans<'t>(text: &'t str, offsets: &[(usize, usize)]) -> Vec<&'t str> {
+        offsets.iter().map(|&(start, end)| &text[start..end]).collect()
+    }
+
+    #[test]
+    fn offsets_survive_normalization() {
+        let vocab = Vocab::builder().unk_token(UNK)
+                                    .normalizer(Normalizer::new().unicode(UnicodeForm::Nfkc).lowercase())
+                                    .build_reader("fine cafe\u{301} day".as_bytes())
+                                    .unwrap();
+        let text = "The \u{fb01}ne CAFÉ, Ünïcode day";
+        let encoding = vocab.encode_with_offsets(text).unwrap();
+        assert_eq!(encoding.tokens(), &["<unk>", "fine", "café", "<unk>", "day"]);
+        assert_eq!(spans(text, encoding.offsets()), vec!["The", "\u{fb01}ne", "CAFÉ", "Ünïcode", "day"]);
+        assert_eq!(encoding.char_offsets(), &[(0, 3), (4, 7), (8, 12), (14, 21), (22, 25)]);
+        assert_eq!(encoding.word_ids(), &[Some(0), Some(1), Some(2), Some(3), Some(4)]);
+        assert_eq!(encoding.ids(), &vocab.encode(text)[..]);
+    }
+
+    #[test]
+    fn ids_match_encode_under_context_rules() {
+        let vocab = Vocab::builder().unk_token(UNK)
+                                    .build_reader("ΟΔΟΣ İstanbul".as_bytes())
+                                    .unwrap();
+        for text in &["ΟΔΟΣ", "İstanbul", "ΟΔΟΣ, İstanbul ΟΔΟΣ"] {
+            let encoding = vocab.encode_with_offsets(text).unwrap();
+            assert_eq!(encoding.ids(), &vocab.encode(text)[..], "{}", text);
+            assert!(!encoding.ids().contains(&0), "{}", text);
+        }
+        let encoding = vocab.encode_with_offsets("ΟΔΟΣ İstanbul").unwrap();
+        assert_eq!(spans("ΟΔΟΣ İstanbul", encoding.offsets()), vec!["ΟΔΟΣ", "İstanbul"]);
+    }
+
+    #[test]
+    fn masks_mark_special_tokens_but_not_unknown_ones() {
+        let vocab = Vocab::builder().special_tokens(&[MASK])
+                                    .unk_token(UNK)
+                                    .normalizer(Normalizer::new())
+                                    .tokenizer(WhitespaceTokenizer)
+                                    .build_reader("the cat sat".as_bytes())
+                                    .unwrap();
+        let encoding = vocab.encode_with_offsets("the <mask> sat on <unk> dog").unwrap();
+        assert_eq!(encoding.tokens(), &["the", "<mask>", "sat", "<unk>", "<unk>", "<unk>"]);
+        assert_eq!(encoding.special_tokens_mask(), &[0, 1, 0, 0, 0, 0]);
+        assert_eq!(encoding.attention_mask(), &[1; 6]);
+        assert_eq!(encoding.type_ids(), &[0; 6]);
+        assert_eq!(encoding.len(), 6);
+    }
+
+    fn letters() -> Vocab {
+        Vocab::builder().special_tokens(&[PAD])
+                        .unk_token(UNK)
+                        .build_reader("a b c d e f g".as_bytes())
+                        .unwrap()
+    }
+
+    fn ids(encodings: &[Encoding]) -> Vec<Vec<i32>> {
+        encodings.iter().map(|encoding| encoding.ids().to_vec()).collect()
+    }
+
+    #[test]
+    fn truncation_keeps_overflowing_windows() {
+        let vocab = letters();
+        let mut encoding = vocab.encode_with_offsets("a b c d e f g").unwrap();
+        let full = encoding.clone();
+        encoding.truncate(3, 1);
+        assert_eq!(encoding.ids(), &[2, 3, 4]);
+        assert_eq!(encoding.offsets(), &full.offsets()[..3]);
+        assert_eq!(ids(encoding.overflowing()), vec![vec![4, 5, 6], vec![6, 7, 8]]);
+        assert_eq!(encoding.overflowing()[1].word_ids(), &[Some(4), Some(5), Some(6)]);
+
+        let mut unstrided = full.clone();
+        unstrided.truncate(3, 0);
+        assert_eq!(ids(unstrided.overflowing()), vec![vec![5, 6, 7], vec![8]]);
+        let mut widest = full.clone();
+        widest.truncate(2, 5);
+        assert_eq!(widest.overflowing().len(), 5);
+        let mut short = full.clone();
+        short.truncate(7, 2);
+        assert_eq!(short, full);
+        short.truncate(0, 0);
+        assert!(short.is_empty());
+        assert_eq!(short.overflowing(), &[full]);
+    }
+
+    #[test]
+    fn padding_fills_every_field() {
+        let vocab = letters();
+        let mut right = vocab.encode_with_offsets("b zz").unwrap();
+        right.pad(4, 0, PAD, PaddingSide::Right);
+        assert_eq!(right.ids(), &[3, 1, 0, 0]);
+        assert_eq!(right.tokens(), &["b", UNK, PAD, PAD]);
+        assert_eq!(right.offsets(), &[(0, 1), (2, 4), (0, 0), (0, 0)]);
+        assert_eq!(right.word_ids(), &[Some(0), Some(1), None, None]);
+        assert_eq!(right.attention_mask(), &[1, 1, 0, 0]);
+        assert_eq!(right.special_tokens_mask(), &[0, 0, 1, 1]);
+        assert_eq!(right.type_ids(), &[0; 4]);
+
+        let mut left = vocab.encode_with_offsets("b zz").unwrap();
+        left.pad(3, 0, PAD, PaddingSide::Left);
+        assert_eq!(left.ids(), &[0, 3, 1]);
+        assert_eq!(left.char_offsets(), &[(0, 0), (0, 1), (2, 4)]);
+        left.pad(2, 0, PAD, PaddingSide::Left);
+        assert_eq!(left.len(), 3);
+    }
+
+    #[test]
+    fn batches_pad_to_one_length() {
+        let texts = ["a b c d e", "f", ""];
+        let vocab = letters().with_truncation(Truncation::new(4).with_stride(2));
+        let batch = vocab.encode_batch(&texts).unwrap();
+        assert_eq!(ids(&batch), vec![vec![2, 3, 4, 5], vec![7], vec![]]);
+        assert_eq!(ids(batch[0].overflowing()), vec![vec![4, 5, 6]]);
+
+        let padded = vocab.clone().with_padding(Padding::longest()).encode_batch(&texts).unwrap();
+        assert_eq!(ids(&padded), vec![vec![2, 3, 4, 5], vec![7, 0, 0, 0], vec![0; 4]]);
+        assert_eq!(ids(padded[0].overflowing()), vec![vec![4, 5, 6, 0]]);
+
+        let fixed = vocab.clone().with_padding(Padding::fixed(2).with_multiple_of(3)).encode_batch(&texts).unwrap();
+        assert_eq!(ids(&fixed), vec![vec![2, 3, 4, 5], vec![7, 0, 0], vec![0; 3]]);
+
+        let untruncated = vocab.without_truncation().with_padding(Padding::longest());
+        let batch = untruncated.encode_batch(&texts).unwrap();
+        assert_eq!(batch[0].ids(), &untruncated.encode(texts[0])[..]);
+        assert_eq!(batch[1].len(), 5);
+        assert!(untruncated.encode_batch::<&str>(&[]).unwrap().is_empty());
+
+        let no_pad = Vocab::from_reader("a b".as_bytes()).unwrap().with_padding(Padding::longest());
+        assert!(matches!(no_pad.encode_batch(&texts), Err(Error::Config { .. })));
+        let custom = no_pad.with_padding(Padding::longest().with_token("b"));
+        assert_eq!(ids(&custom.encode_batch(&["a a", "a"]).unwrap()), vec![vec![0, 0], vec![0, 1]]);
+    }
+
+    fn bert_letters() -> Vocab {
+        Vocab::builder().special_tokens(&["[CLS]", "[SEP]"])
+                        .unk_token(UNK)
+                        .build_reader("a b c d e f g".as_bytes())
+                        .unwrap()
+    }
+
+    #[test]
+    fn templates_place_special_tokens() {
+        let vocab = bert_letters().with_template(Template::bert()).unwrap();
+        let pair = vocab.encode_pair("a b", "c").unwrap();
+        assert_eq!(pair.tokens(), &["[CLS]", "a", "b", "[SEP]", "c", "[SEP]"]);
+        assert_eq!(pair.ids(), &[0, 3, 4, 1, 5, 1]);
+        assert_eq!(pair.type_ids(), &[0, 0, 0, 0, 1, 1]);
+        assert_eq!(pair.special_tokens_mask(), &[1, 0, 0, 1, 0, 1]);
+        assert_eq!(pair.attention_mask(), &[1; 6]);
+        assert_eq!(pair.sequence_ids(), &[None, Some(0), Some(0), None, Some(1), None]);
+        assert_eq!(pair.offsets(), &[(0, 0), (0, 1), (2, 3), (0, 0), (0, 1), (0, 0)]);
+        assert_eq!(pair.word_ids(), &[None, Some(0), Some(1), None, Some(0), None]);
+        assert_eq!(vocab.encode_with_offsets("a").unwrap().ids(), &[0, 3, 1]);
+
+        let truncated = vocab.with_truncation(Truncation::new(5));
+        let single = truncated.encode_with_offsets("a b c d e").unwrap();
+        assert_eq!(single.ids(), &[0, 3, 4, 5, 1]);
+        assert_eq!(ids(single.overflowing()), vec![vec![0, 6, 7, 1]]);
+        assert_eq!(truncated.encode_pair("a b", "c d").unwrap().ids(), &[0, 3, 1, 5, 1]);
+
+        let missing = Template::new("<s> $A", "<s> $A $B").unwrap();
+        assert!(matches!(bert_letters().with_template(missing), Err(Error::Config { .. })));
+    }
+
+    #[test]
+    fn truncation_leaves_room_for_the_template() {
+        let vocab = bert_letters().with_template(Template::bert()).unwrap();
+        let tight = vocab.clone().with_truncation(Truncation::new(3));
+        assert_eq!(tight.encode_with_offsets("a b").unwrap().ids(), &[0, 3, 1]);
+        assert!(matches!(tight.encode_pair("a", "b"), Err(Error::Config { .. })));
+        assert!(matches!(tight.encode_pair_batch(&[("a", "b")]), Err(Error::Config { .. })));
+
+        let full = vocab.with_truncation(Truncation::new(2));
+        assert!(matches!(full.encode_with_offsets(""), Err(Error::Config { .. })));
+        assert!(matches!(full.encode_batch(&["a"]), Err(Error::Config { .. })));
+        let zero = letters().with_truncation(Truncation::new(0));
+        assert!(matches!(zero.encode_with_offsets("a"), Err(Error::Config { .. })));
+    }
+
+    #[test]
+    fn pairs_are_cut_by_strategy() {
+        let vocab = bert_letters().with_truncation(Truncation::new(5));
+        let pair = vocab.encode_pair("a b c d e f", "g").unwrap();
+        assert_eq!(pair.ids(), &[3, 4, 5, 6, 9]);
+        assert_eq!(pair.type_ids(), &[0, 0, 0, 0, 1]);
+        assert_eq!(ids(pair.overflowing()), vec![vec![7, 8, 9]]);
+        assert_eq!(vocab.encode_pair("a b c", "d e f").unwrap().ids(), &[3, 4, 6, 7, 8]);
+        assert_eq!(vocab.encode_pair("a b", "c").unwrap().ids(), &[3, 4, 5]);
+
+        let strategy = |strategy| bert_letters().with_truncation(Truncation::new(5).with_strategy(strategy));
+        let only_first = strategy(TruncationStrategy::OnlyFirst);
+        assert_eq!(only_first.encode_pair("a b c d", "e f").unwrap().ids(), &[3, 4, 5, 7, 8]);
+        assert!(matches!(only_first.encode_pair("a", "c d e f g"), Err(Error::Truncation { .. })));
+        assert!(matches!(only_first.encode_pair("a b", "c d e f g h"), Err(Error::Truncation { .. })));
+        let only_second = strategy(TruncationStrategy::OnlySecond);
+        assert_eq!(only_second.encode_pair("a b", "c d e f g").unwrap().ids(), &[3, 4, 5, 6, 7]);
+        assert!(matches!(only_second.encode_pair("a b c d e", "f"), Err(Error::Truncation { .. })));
+        assert!(matches!(only_second.encode_pair_batch(&[("a", "b"), ("a b c d e f", "g")]), Err(Error::Truncation { .. })));
+
+        let tiny = bert_letters().with_truncation(Truncation::new(1));
+        assert!(matches!(tiny.encode_pair("a b c d e", "b c d e f"), Err(Error::Truncation { .. })));
+        assert_eq!(tiny.encode_pair("", "b c").unwrap().ids(), &[4]);
+
+        for strategy in [TruncationStrategy::LongestFirst, TruncationStrategy::OnlyFirst, TruncationStrategy::OnlySecond] {
+            let bert = bert_letters().with_template(Template::bert())
+                                     .unwrap()
+                                     .with_truncation(Truncation::new(4).with_strategy(strategy));
+            assert!(matches!(bert.encode_pair("a b c", "d e f"), Err(Error::Truncation { .. })), "{:?}", strategy);
+            assert!(matches!(bert.encode_pair_batch(&[("a", "b c d e f g")]), Err(Error::Truncation { .. })));
+        }
+
+        let batch = vocab.encode_pair_batch(&[("a", "b"), ("c d e", "f")]).unwrap();
+        assert_eq!(ids(&batch), vec![vec![3, 4], vec![5, 6, 7, 8]]);
+    }
+
+    #[test]
+    fn templates_are_saved_with_the_vocabulary() {
+        let vocab = bert_letters().with_template(Template::new("[CLS] $A", "[CLS] $A [SEP]:1 $B").unwrap())
+                                  .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let tsv = dir.path().join("vocab.tsv");
+        let bin = dir.path().join("vocab.bin");
+        vocab.write(tsv.to_str().unwrap()).unwrap();
+        vocab.write_binary(bin.to_str().unwrap()).unwrap();
+
+        let expected = vocab.encode_pair("a b", "c g").unwrap();
+        for path in &[&tsv, &bin] {
+            let loaded = Vocab::load(path.to_str().unwrap()).unwrap();
+            assert_eq!(loaded, vocab);
+            assert_eq!(loaded.encode_pair("a b", "c g").unwrap(), expected);
+        }
+        let mapped = MappedVocab::open(&bin).unwrap();
+        assert_eq!(mapped.template(), vocab.template());
+        assert_eq!(mapped.encode_pair("a b", "c g").unwrap(), expected);
+        assert_eq!(expected.type_ids(), &[0, 0, 0, 1, 1, 1]);
+        assert_ne!(bert_letters(), vocab);
+    }
+
+    #[test]
+    fn subword_pieces_cover_part_of_their_term() {
+        let vocab = Vocab::from_bert("[UNK]\nun\n##aff\n##able\nflat\n").unwrap();
+        let text = "Flat UNAFFABLE";
+        let encoding = vocab.encode_with_offsets(text).unwrap();
+        assert_eq!(encoding.tokens(), &["flat", "un", "##aff", "##able"]);
+        assert_eq!(spans(text, encoding.offsets()), vec!["Flat", "UN", "AFF", "ABLE"]);
+        assert_eq!(encoding.word_ids(), &[Some(0), Some(1), Some(1), Some(1)]);
+        assert_eq!(encoding.word_to_tokens(1), Some((1, 4)));
+        assert_eq!(encoding.word_to_tokens(2), None);
+        assert_eq!(encoding.char_to_token(7), Some(2));
+        assert_eq!(encoding.char_to_token(4), None);
+    }
+
+    #[test]
+    fn byte_level_pieces_cover_whole_characters() {
+        let words = Vocab::builder().normalizer(Normalizer::new())
+                                    .tokenizer(RegexTokenizer::gpt2())
+                                    .build_reader("naïve naïve café".as_bytes())
+                                    .unwrap();
+        let vocab = BpeTrainer::new(300).min_frequency(1).byte_level().train(&words).unwrap();
+        let text = "a naïve café";
+        let encoding = vocab.encode_with_offsets(text).unwrap();
+        assert_eq!(spans(text, encoding.offsets()), vec!["a", " naïve", " café"]);
+        assert_eq!(encoding.ids(), &vocab.encode(text)[..]);
+
+        let split = vocab.clone().with_model(Model::Bpe(Bpe::new(Vec::new(), None).with_byte_level(true)));
+        let encoding = split.encode_with_offsets("ï").unwrap();
+        assert_eq!(encoding.offsets(), &[(0, 1), (1, 2)]);
+        assert_eq!(encoding.char_offsets(), &[(0, 1), (0, 1)]);
+    }
+
+    #[test]
+    fn character_ngrams_point_at_their_characters() {
+        let vocab = Vocab::builder().normalizer(Normalizer::new())
+                                    .model(Model::CharNgrams(CharNgrams::new(3, 3)))
+                                    .build_reader("ab".as_bytes())
+                                    .unwrap();
+        let encoding = vocab.encode_with_offsets(" ab").unwrap();
+        assert_eq!(encoding.tokens(), &["<ab>", "<ab", "ab>"]);
+        assert_eq!(encoding.offsets(), &[(1, 3), (1, 3), (1, 3)]);
+        assert!(vocab.encode_with_offsets("").unwrap().is_empty());
+    }
+}
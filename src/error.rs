@@ -0,0 +1,110 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Result alias used throughout `tok`
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors raised while building, loading or writing a vocabulary
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing `path` failed
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A glob pattern for input files was malformed
+    Pattern {
+        pattern: String,
+        reason: String,
+    },
+    /// An option, such as a normalizer step, was malformed
+    Config {
+        reason: String,
+    },
+    /// A text of a pair could not be cut down to the maximum length
+    Truncation {
+        reason: String,
+    },
+    /// The thread pool for parallel counting could not be started
+    Threads {
+        reason: String,
+    },
+    /// A saved vocabulary had a malformed line
+    ///
+    /// `line` is 1-based and `text` is the offending line verbatim.
+    Parse {
+        line: usize,
+        text: String,
+        reason: String,
+    },
+    /// A binary vocabulary was truncated, damaged or of an unknown version
+    Corrupt {
+        reason: String,
+    },
+    /// A saved vocabulary listed the same term twice
+    DuplicateTerm {
+        line: usize,
+        term: String,
+    },
+    /// A saved vocabulary assigned the same id to two terms
+    DuplicateId {
+        line: usize,
+        id: i32,
+    },
+}
+
+impl Error {
+    /// Wrap an I/O error with the path it occurred on
+    pub(crate) fn io<P: Into<PathBuf>>(path: P, source: io::Error) -> Self {
+        Error::Io { path: path.into(), source }
+    }
+
+    /// Build a parse error for a line of a saved vocabulary
+    pub(crate) fn parse<R: Into<String>>(line: usize, text: &str, reason: R) -> Self {
+        Error::Parse { line, text: text.to_owned(), reason: reason.into() }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+            Error::Pattern { pattern, reason } => {
+                write!(f, "invalid glob {:?}: {}", pattern, reason)
+            }
+            Error::Config { reason } => {
+                write!(f, "invalid configuration: {}", reason)
+            }
+            Error::Truncation { reason } => {
+                write!(f, "cannot truncate: {}", reason)
+            }
+            Error::Threads { reason } => {
+                write!(f, "cannot start thread pool: {}", reason)
+            }
+            Error::Parse { line, text, reason } => {
+                write!(f, "line {}: {} in {:?}", line, reason, text)
+            }
+            Error::Corrupt { reason } => {
+                write!(f, "corrupt binary vocabulary: {}", reason)
+            }
+            Error::DuplicateTerm { line, term } => {
+                write!(f, "line {}: duplicate term {:?}", line, term)
+            }
+            Error::DuplicateId { line, id } => {
+                write!(f, "line {}: duplicate id {}", line, id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
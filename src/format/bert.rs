@@ -0,0 +1,77 @@
+//! BERT `vocab.txt` files
+//!
+//! One term per line, with ids given by line order starting at 0:
+//!
+//! ```text
+//! [PAD]
+//! [UNK]
+//! the
+//! ##s
+//! ```
+//!
+//! `[PAD]`, `[UNK]`, `[CLS]`, `[SEP]` and `[MASK]` are special tokens
+//! when present, and `[UNK]` is the unknown token. Vocabularies read
+//! this way split terms with [`WordPiece`](crate::WordPiece).
+
+use crate::error::Result;
+use crate::format::{VocabParts, SPECIAL_FLAG, UNK_FLAG};
+use crate::model::{Model, WordPiece};
+use crate::vocab::Vocab;
+
+/// Special tokens of BERT vocabularies
+const SPECIALS: [&str; 5] = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "[MASK]"];
+/// Unknown token of BERT vocabularies
+const UNK: &str = "[UNK]";
+
+/// Render a vocabulary with one term per line, in id order
+///
+/// Ids are implied by position, so gaps between ids are not kept.
+pub(crate) fn render(vocab: &Vocab) -> String {
+    let mut contents = String::new();
+    for (term, _) in vocab.entries() {
+        contents.push_str(term);
+        contents.push('\n');
+    }
+
+    contents
+}
+
+/// Parse one term per line
+pub(crate) fn parse(contents: &str) -> Result<Vocab> {
+    let mut parts = VocabParts::default();
+    for (idx, line) in contents.lines().enumerate() {
+        let term = line.strip_suffix('\r').unwrap_or(line);
+        parts.insert(idx + 1, term.to_owned(), idx as i32)?;
+        let flag = match term {
+            UNK => UNK_FLAG,
+            _ if SPECIALS.contains(&term) => SPECIAL_FLAG,
+            _ => "",
+        };
+        parts.flag(idx + 1, line, term, flag)?;
+    }
+
+    Ok(parts.finish()?.with_model(Model::WordPiece(WordPiece::new())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::error::Error;
+
+    #[test]
+    fn reads_terms_in_line_order() {
+        let vocab = parse("[PAD]\r\n[UNK]\n[CLS]\nthe\n##s\n").unwrap();
+        assert_eq!(vocab.size(), 5);
+        assert_eq!(vocab.token_to_id("##s"), Some(4));
+        assert_eq!(vocab.unk_id(), Some(1));
+        assert_eq!(vocab.special_tokens(), &["[PAD]", "[UNK]", "[CLS]"]);
+        assert_eq!(*vocab.model(), Model::WordPiece(WordPiece::new()));
+        assert_eq!(render(&vocab), "[PAD]\n[UNK]\n[CLS]\nthe\n##s\n");
+    }
+
+    #[test]
+    fn rejects_repeated_terms() {
+        assert!(matches!(parse("a\nb\na\n"), Err(Error::DuplicateTerm { line: 3, .. })));
+    }
+}
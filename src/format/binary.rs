@@ -0,0 +1,419 @@
+//! Binary vocabulary files
+//!
+//! The layout is designed to be memory-mapped and queried in place.
+//! All integers are little-endian.
+//!
+//! ```text
+//! header   magic "TOKVOCAB", version u32, count u32, unk i32,
+//!          flags u32, arena length u64, crc32 u32, settings length u32
+//! entries  count x (id i32, flags u32, length u32, reserved u32,
+//!          offset u64), sorted by id
+//! index    count x (hash u64, entry u32), sorted by hash
+//! arena    UTF-8 bytes of every term, back to back
+//! settings UTF-8 lines of a setting name, a tab and its JSON value
+//! ```
+//!
+//! The checksum covers everything after the header. Terms are hashed
+//! with 64-bit FNV-1a, which is stable across processes and platforms.
+//! Version 1 files have no settings, and their settings length is 0.
+
+use std::convert::{TryFrom, TryInto};
+
+use crate::error::{Error, Result};
+use crate::format::{Settings, VocabParts, SPECIAL_FLAG, UNK_FLAG};
+use crate::vocab::Vocab;
+
+/// First bytes of every binary vocabulary
+pub(crate) const MAGIC: &[u8; 8] = b"TOKVOCAB";
+/// Version written by [`render`]
+const VERSION: u32 = 2;
+/// Last version without a settings section
+const VERSION_1: u32 = 1;
+
+const HEADER_LEN: usize = 40;
+const ENTRY_LEN: usize = 24;
+const INDEX_LEN: usize = 12;
+
+/// Header flag: the `unk` field holds the unknown token id
+const HAS_UNK: u32 = 1;
+/// Entry flag: the term is a special token
+const SPECIAL: u32 = 1;
+
+/// Render a vocabulary in the binary format
+pub(crate) fn render(vocab: &Vocab) -> Vec<u8> {
+    let entries = vocab.entries();
+    let arena_len: usize = entries.iter().map(|(voc, _)| voc.len()).sum();
+
+    let mut body = Vec::with_capacity(entries.len() * (ENTRY_LEN + INDEX_LEN) + arena_len);
+    let mut offset = 0u64;
+    for &(voc, tok) in &entries {
+        let flags = if vocab.is_special(voc) { SPECIAL } else { 0 };
+        body.extend_from_slice(&tok.to_le_bytes());
+        body.extend_from_slice(&flags.to_le_bytes());
+        body.extend_from_slice(&(voc.len() as u32).to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&offset.to_le_bytes());
+        offset += voc.len() as u64;
+    }
+
+    let mut index: Vec<(u64, u32)> = entries.iter()
+                                            .enumerate()
+                                            .map(|(entry, (voc, _))| (hash(voc), entry as u32))
+                                            .collect();
+    index.sort_unstable();
+    for (hash, entry) in index {
+        body.extend_from_slice(&hash.to_le_bytes());
+        body.extend_from_slice(&entry.to_le_bytes());
+    }
+
+    for (voc, _) in &entries {
+        body.extend_from_slice(voc.as_bytes());
+    }
+
+    let mut settings = String::new();
+    for (key, value) in Settings::render(vocab) {
+        settings.push_str(key);
+        settings.push('\t');
+        settings.push_str(&value);
+        settings.push('\n');
+    }
+    body.extend_from_slice(settings.as_bytes());
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&vocab.unk_id().unwrap_or(0).to_le_bytes());
+    bytes.extend_from_slice(&(if vocab.unk_id().is_some() { HAS_UNK } else { 0 }).to_le_bytes());
+    bytes.extend_from_slice(&(arena_len as u64).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    bytes.extend_from_slice(&(settings.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&body);
+
+    bytes
+}
+
+/// Parse a binary vocabulary into an owned [`Vocab`]
+pub(crate) fn parse(bytes: &[u8]) -> Result<Vocab> {
+    let layout = Layout::parse(bytes)?;
+    layout.verify(bytes)?;
+
+    let mut parts = VocabParts { settings: layout.settings(bytes)?, ..VocabParts::default() };
+    for idx in 0..layout.count {
+        let entry = layout.entry(bytes, idx);
+        if layout.unk == Some(entry.id) {
+            parts.flag(idx + 1, entry.term, entry.term, UNK_FLAG)?;
+        } else if entry.special {
+            parts.flag(idx + 1, entry.term, entry.term, SPECIAL_FLAG)?;
+        }
+        parts.insert(idx + 1, entry.term.to_owned(), entry.id)?;
+    }
+
+    parts.finish()
+}
+
+/// Whether `bytes` start like a binary vocabulary
+pub(crate) fn sniff(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// 64-bit FNV-1a hash of a term
+fn hash(term: &str) -> u64 {
+    term.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A vocabulary entry read in place
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Entry<'a> {
+    pub(crate) id: i32,
+    pub(crate) special: bool,
+    pub(crate) term: &'a str,
+}
+
+/// Section positions of a binary vocabulary
+///
+/// A `Layout` only holds offsets, so it can live next to the buffer
+/// it describes, such as a memory map.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Layout {
+    pub(crate) count: usize,
+    pub(crate) unk: Option<i32>,
+    index: usize,
+    arena: usize,
+    settings: usize,
+}
+
+impl Layout {
+    /// Check the header of `bytes` and that every section fits in them
+    ///
+    /// This takes the same time whatever the size of the file. Once it
+    /// succeeds, lookups through the layout cannot go out of bounds, but
+    /// they may find the wrong entry until [`Layout::verify`] succeeds.
+    pub(crate) fn parse(bytes: &[u8]) -> Result<Layout> {
+        if bytes.len() < HEADER_LEN || !sniff(bytes) {
+            return Err(corrupt("missing header"));
+        }
+        let version = u32_at(bytes, 8);
+        if version != VERSION && version != VERSION_1 {
+            return Err(corrupt("unsupported format version"));
+        }
+        let count = u32_at(bytes, 12) as usize;
+        let unk = if u32_at(bytes, 20) & HAS_UNK != 0 { Some(u32_at(bytes, 16) as i32) } else { None };
+        let arena_len = u64_at(bytes, 24);
+        let settings_len = if version == VERSION_1 { 0 } else { u32_at(bytes, 36) as u64 };
+
+        // Lengths come from the file, so none of the sums may overflow.
+        let arena = count.checked_mul(ENTRY_LEN + INDEX_LEN).and_then(|len| len.checked_add(HEADER_LEN));
+        let body = arena_len.checked_add(settings_len).and_then(|len| usize::try_from(len).ok());
+        let (arena, body) = match (arena, body) {
+            (Some(arena), Some(body)) if arena.checked_add(body) == Some(bytes.len()) => (arena, body),
+            _ => return Err(corrupt("truncated or oversized file")),
+        };
+        let index = HEADER_LEN + count * ENTRY_LEN;
+        // The arena is part of the body, whose length fits in `usize`.
+        let settings = arena + (body - settings_len as usize);
+        if std::str::from_utf8(&bytes[settings..]).is_err() {
+            return Err(corrupt("settings are not UTF-8"));
+        }
+
+        let layout = Layout { count, unk, index, arena, settings };
+        if layout.unk.is_some_and(|unk| layout.find_id(bytes, unk).is_none()) {
+            return Err(corrupt("unk id is not an entry"));
+        }
+
+        Ok(layout)
+    }
+
+    /// Check the checksum, every entry and the hash index of `bytes`
+    ///
+    /// This reads the whole file, so it takes time in proportion to its
+    /// size.
+    pub(crate) fn verify(&self, bytes: &[u8]) -> Result<()> {
+        if crc32fast::hash(&bytes[HEADER_LEN..]) != u32_at(bytes, 32) {
+            return Err(corrupt("checksum mismatch"));
+        }
+
+        let arena_len = (self.settings - self.arena) as u64;
+        let mut previous = None;
+        for idx in 0..self.count {
+            let at = HEADER_LEN + idx * ENTRY_LEN;
+            let id = u32_at(bytes, at) as i32;
+            if previous.is_some_and(|prev| prev >= id) {
+                return Err(corrupt("entries not sorted by id"));
+            }
+            previous = Some(id);
+
+            let start = u64_at(bytes, at + 16);
+            let end = match start.checked_add(u32_at(bytes, at + 8) as u64) {
+                Some(end) if end <= arena_len => end,
+                _ => return Err(corrupt("term outside the string arena")),
+            };
+            let start = self.arena + start as usize;
+            let end = self.arena + end as usize;
+            if std::str::from_utf8(&bytes[start..end]).is_err() {
+                return Err(corrupt("term is not UTF-8"));
+            }
+        }
+        let mut previous = 0;
+        for slot in 0..self.count {
+            let (hash, entry) = self.slot(bytes, slot);
+            if hash < previous || entry >= self.count {
+                return Err(corrupt("malformed hash index"));
+            }
+            previous = hash;
+        }
+
+        Ok(())
+    }
+
+    /// Parse the settings section
+    pub(crate) fn settings(&self, bytes: &[u8]) -> Result<Settings> {
+        Settings::parse_lines(std::str::from_utf8(&bytes[self.settings..]).unwrap_or_default())
+    }
+
+    /// Read the entry at position `idx` of the id-sorted table
+    ///
+    /// A term that lies outside the arena or is not UTF-8 reads as empty.
+    pub(crate) fn entry<'a>(&self, bytes: &'a [u8], idx: usize) -> Entry<'a> {
+        let at = HEADER_LEN + idx * ENTRY_LEN;
+        let arena = &bytes[self.arena..self.settings];
+        let start = u64_at(bytes, at + 16);
+        let term = start.checked_add(u32_at(bytes, at + 8) as u64)
+                        .filter(|&end| end <= arena.len() as u64)
+                        .and_then(|end| std::str::from_utf8(&arena[start as usize..end as usize]).ok());
+
+        Entry {
+            id: u32_at(bytes, at) as i32,
+            special: u32_at(bytes, at + 4) & SPECIAL != 0,
+            term: term.unwrap_or_default(),
+        }
+    }
+
+    /// Find the entry with token `id` by binary search
+    pub(crate) fn find_id(&self, bytes: &[u8], id: i32) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let found = u32_at(bytes, HEADER_LEN + mid * ENTRY_LEN) as i32;
+            match found.cmp(&id) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+
+        None
+    }
+
+    /// Find the entry for `term` through the hash index
+    pub(crate) fn find_term(&self, bytes: &[u8], term: &str) -> Option<usize> {
+        let hash = hash(term);
+        let (mut lo, mut hi) = (0, self.count);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.slot(bytes, mid).0 < hash {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        (lo..self.count).map(|slot| self.slot(bytes, slot))
+                        .take_while(|&(found, _)| found == hash)
+                        .map(|(_, entry)| entry)
+                        .find(|&entry| entry < self.count && self.entry(bytes, entry).term == term)
+    }
+
+    fn slot(&self, bytes: &[u8], slot: usize) -> (u64, usize) {
+        let at = self.index + slot * INDEX_LEN;
+        (u64_at(bytes, at), u32_at(bytes, at + 8) as usize)
+    }
+}
+
+fn corrupt(reason: &str) -> Error {
+    Error::Corrupt { reason: reason.to_owned() }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap_or_default())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    fn sample() -> Vocab {
+        Vocab::builder().special_tokens(&["<pad>"])
+                        .unk_token("<unk>")
+                        .build_reader("the café, the thé".as_bytes())
+                        .unwrap()
+    }
+
+    #[test]
+    fn lookups_work_in_place() {
+        let bytes = render(&sample());
+        let layout = Layout::parse(&bytes).unwrap();
+        assert_eq!(layout.count, 5);
+        assert_eq!(layout.unk, Some(1));
+
+        let idx = layout.find_term(&bytes, "café").unwrap();
+        assert_eq!(layout.entry(&bytes, idx).id, 3);
+        assert_eq!(layout.entry(&bytes, layout.find_id(&bytes, 4).unwrap()).term, "thé");
+        assert!(layout.entry(&bytes, layout.find_id(&bytes, 0).unwrap()).special);
+        assert_eq!(layout.find_term(&bytes, "tea"), None);
+        assert_eq!(layout.find_id(&bytes, 5), None);
+    }
+
+    #[test]
+    fn parse_restores_specials() {
+        let vocab = sample();
+        assert_eq!(parse(&render(&vocab)).unwrap(), vocab);
+    }
+
+    #[test]
+    fn reads_version_1_files() {
+        let vocab = sample().with_normalizer(crate::Normalizer::new());
+        let mut bytes = render(&vocab);
+        let settings = u32_at(&bytes, 36) as usize;
+        bytes.truncate(bytes.len() - settings);
+        bytes[8..12].copy_from_slice(&VERSION_1.to_le_bytes());
+        bytes[36..40].copy_from_slice(&0u32.to_le_bytes());
+        let crc = crc32fast::hash(&bytes[HEADER_LEN..]);
+        bytes[32..36].copy_from_slice(&crc.to_le_bytes());
+
+        let parsed = parse(&bytes).unwrap();
+        assert_eq!(parsed.entries(), vocab.entries());
+        assert_eq!(parsed.normalizer(), &crate::Normalizer::default());
+        assert_eq!(parse(&render(&vocab)).unwrap(), vocab);
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let bytes = render(&sample());
+        let corrupted = |bytes: &[u8]| {
+            matches!(Layout::parse(bytes).and_then(|layout| layout.verify(bytes)), Err(Error::Corrupt { .. }))
+        };
+
+        assert!(corrupted(&bytes[..HEADER_LEN - 1]));
+        assert!(corrupted(&bytes[..bytes.len() - 1]));
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 0xff;
+        assert!(corrupted(&flipped));
+
+        let mut version = bytes.clone();
+        version[8] = 9;
+        assert!(corrupted(&version));
+    }
+
+    #[test]
+    fn rejects_lengths_that_overflow() {
+        let bytes = render(&sample());
+        let body = (bytes.len() - HEADER_LEN - 5 * (ENTRY_LEN + INDEX_LEN)) as u64;
+        let with_lengths = |arena_len: u64, settings_len: u32| {
+            let mut bytes = bytes.clone();
+            bytes[24..32].copy_from_slice(&arena_len.to_le_bytes());
+            bytes[36..40].copy_from_slice(&settings_len.to_le_bytes());
+            bytes
+        };
+
+        for (arena_len, settings_len) in [(u64::MAX, u32::MAX),
+                                          (u64::MAX, 1),
+                                          (body.wrapping_sub(u32::MAX as u64), u32::MAX),
+                                          (u64::MAX - u32::MAX as u64 + body + 1, u32::MAX)] {
+            let bytes = with_lengths(arena_len, settings_len);
+            assert!(matches!(Layout::parse(&bytes), Err(Error::Corrupt { .. })), "{} {}", arena_len, settings_len);
+        }
+    }
+
+    #[test]
+    fn fnv_is_stable() {
+        assert_eq!(hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    proptest! {
+        #[test]
+        fn any_terms_round_trip(terms in prop::collection::hash_set("(?s).*", 0..30)) {
+            let vocab = Vocab::from_json(&serde_json::to_string(
+                &terms.iter().enumerate().map(|(tok, voc)| (voc.clone(), tok * 2)).collect::<std::collections::HashMap<_, _>>()
+            ).unwrap()).unwrap();
+            let bytes = render(&vocab);
+            let layout = Layout::parse(&bytes).unwrap();
+            for (voc, tok) in vocab.entries() {
+                let idx = layout.find_term(&bytes, voc).unwrap();
+                prop_assert_eq!(layout.entry(&bytes, idx).id, tok);
+                prop_assert_eq!(layout.entry(&bytes, layout.find_id(&bytes, tok).unwrap()).term, voc);
+            }
+            prop_assert_eq!(parse(&bytes).unwrap(), vocab);
+        }
+    }
+}
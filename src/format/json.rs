@@ -0,0 +1,143 @@
+//! JSON vocabulary files
+//!
+//! A single object mapping each term to its id, the layout of the
+//! `vocab.json` files used by Hugging Face tokenizers:
+//!
+//! ```text
+//! {"<unk>":0,"the":1,"cat":2}
+//! ```
+//!
+//! The format has no room for special token flags, frequencies, the
+//! normalizer or the model, so those are dropped on write and defaults
+//! are used after a read.
+
+use std::fmt;
+
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+
+use crate::error::{Error, Result};
+use crate::format::VocabParts;
+use crate::vocab::Vocab;
+
+/// Render a vocabulary as a JSON object, with entries sorted by id
+pub(crate) fn render(vocab: &Vocab) -> String {
+    let mut contents = String::from("{");
+    for (idx, (voc, tok)) in vocab.entries().into_iter().enumerate() {
+        if idx > 0 {
+            contents.push(',');
+        }
+        contents.push_str(&serde_json::to_string(voc).unwrap_or_default());
+        contents.push(':');
+        contents.push_str(&tok.to_string());
+    }
+    contents.push('}');
+
+    contents
+}
+
+/// Parse a JSON object of terms to ids
+pub(crate) fn parse(contents: &str) -> Result<Vocab> {
+    let mut de = serde_json::Deserializer::from_str(contents);
+    let parts = de.deserialize_map(PartsVisitor)
+                  .and_then(|parts| de.end().map(|_| parts))
+                  .map_err(|e| {
+                      let text = contents.lines().nth(e.line().saturating_sub(1)).unwrap_or_default();
+                      Error::parse(e.line(), text, e.to_string())
+                  })?;
+
+    parts.finish()
+}
+
+/// Whether `contents` is a JSON object rather than TSV
+///
+/// Headerless TSV may start with a term such as `{`, so the whole text
+/// must parse as an object, though its entries are not checked.
+pub(crate) fn sniff(contents: &str) -> bool {
+    let mut de = serde_json::Deserializer::from_str(contents);
+    de.deserialize_map(de::IgnoredAny).and_then(|_| de.end()).is_ok()
+}
+
+/// Reads the object entry by entry, so repeated terms are caught
+/// with the position serde_json reports
+struct PartsVisitor;
+
+impl<'de> Visitor<'de> for PartsVisitor {
+    type Value = VocabParts;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an object mapping terms to integer ids")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut entries: A) -> std::result::Result<VocabParts, A::Error> {
+        let mut parts = VocabParts::default();
+        while let Some((voc, tok)) = entries.next_entry::<String, i32>()? {
+            parts.insert(0, voc, tok).map_err(|e| match e {
+                Error::DuplicateTerm { term, .. } => {
+                    de::Error::custom(format!("duplicate term {:?}", term))
+                }
+                Error::DuplicateId { id, .. } => de::Error::custom(format!("duplicate id {}", id)),
+                e => de::Error::custom(e),
+            })?;
+        }
+
+        Ok(parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    fn reason(contents: &str) -> (usize, String) {
+        match parse(contents) {
+            Err(Error::Parse { line, reason, .. }) => (line, reason),
+            other => panic!("expected Parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn renders_sorted_by_id() {
+        let vocab = parse(r#"{"b": 1, "a\"q": 2, "<unk>": 0}"#).unwrap();
+        assert_eq!(render(&vocab), r#"{"<unk>":0,"b":1,"a\"q":2}"#);
+    }
+
+    #[test]
+    fn reports_position_of_bad_entries() {
+        let (line, text) = reason("{\n\"a\": 0,\n\"a\": 1}");
+        assert_eq!(line, 3);
+        assert!(text.contains("duplicate term"), "{}", text);
+
+        let (line, text) = reason("{\"a\": 0,\n\"b\": 0}");
+        assert_eq!(line, 2);
+        assert!(text.contains("duplicate id"), "{}", text);
+
+        assert_eq!(reason("{\"a\": \"zero\"}").0, 1);
+        assert_eq!(reason("{\"a\": 0} trailing").0, 1);
+        assert_eq!(reason("[\"a\"]").0, 1);
+    }
+
+    #[test]
+    fn sniffs_objects() {
+        assert!(sniff("  \n{\"a\": 0}"));
+        assert!(!sniff("#tok-vocab 1\tterm\tid\n"));
+        assert!(!sniff("a\t0\n"));
+        assert!(!sniff("{\t0\n}\t1\n"));
+        assert!(!sniff("{\"a\": 0}\t1\n"));
+        assert!(!sniff("[\"a\"]"));
+    }
+
+    proptest! {
+        #[test]
+        fn any_terms_round_trip(terms in prop::collection::hash_set("(?s).*", 0..20)) {
+            let object: serde_json::Map<String, serde_json::Value> = terms.iter()
+                .enumerate()
+                .map(|(tok, voc)| (voc.to_owned(), tok.into()))
+                .collect();
+            let vocab = parse(&serde_json::to_string(&object).unwrap()).unwrap();
+            prop_assert_eq!(vocab.size(), terms.len());
+            prop_assert_eq!(parse(&render(&vocab)).unwrap(), vocab);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! On-disk vocabulary formats
+//!
+//! Each format lives in its own module and converts between a file's
+//! contents and a [`Vocab`]. [`VocabParts`] collects entries while a
+//! file is parsed and applies the checks every format shares.
+//! [`Settings`] carries the options saved next to the entries.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::error::{Error, Result};
+use crate::model::Model;
+use crate::normalizer::Normalizer;
+use crate::template::Template;
+use crate::tokenizer::SharedTokenizer;
+use crate::vocab::Vocab;
+
+pub(crate) mod bert;
+pub(crate) mod binary;
+pub(crate) mod json;
+pub(crate) mod tsv;
+
+/// Flag marking a special token
+pub(crate) const SPECIAL_FLAG: &str = "special";
+/// Flag marking the unknown token
+pub(crate) const UNK_FLAG: &str = "unk";
+
+/// Setting holding the normalizer step names
+pub(crate) const NORMALIZER: &str = "normalizer";
+/// Setting holding the model splitting terms into entries
+pub(crate) const MODEL: &str = "model";
+/// Setting holding a built-in tokenizer other than the default one
+pub(crate) const TOKENIZER: &str = "tokenizer";
+/// Setting holding the post-processing template, saved only when set
+pub(crate) const TEMPLATE: &str = "template";
+
+/// Options saved alongside the entries of a vocabulary
+///
+/// Each setting is stored as a key and a compact JSON value, which
+/// never contains a tab or a newline. Options missing from a file
+/// keep their defaults, so older files load as before.
+#[derive(Debug, Default)]
+pub(crate) struct Settings {
+    normalizer: Option<Normalizer>,
+    model: Option<Model>,
+    tokenizer: Option<SharedTokenizer>,
+    template: Option<Template>,
+}
+
+impl Settings {
+    /// The settings to save for `vocab`
+    pub(crate) fn render(vocab: &Vocab) -> Vec<(&'static str, String)> {
+        let names = vocab.normalizer().names();
+        let mut settings = vec![(NORMALIZER, serde_json::to_string(&names).unwrap_or_default()),
+                                (MODEL, vocab.model().to_json().to_string())];
+        let tokenizer = vocab.shared_tokenizer();
+        if let Some(tokenizer) = tokenizer.to_json().filter(|_| !tokenizer.is_default()) {
+            settings.push((TOKENIZER, tokenizer.to_string()));
+        }
+        if let Some(template) = vocab.template() {
+            settings.push((TEMPLATE, template.to_json().to_string()));
+        }
+
+        settings
+    }
+
+    /// Read one saved setting
+    ///
+    /// `line` and `text` locate the setting in errors.
+    pub(crate) fn set(&mut self, line: usize, text: &str, key: &str, value: &str) -> Result<()> {
+        match key {
+            NORMALIZER if self.normalizer.is_none() => {
+                let names: Vec<String> = serde_json::from_str(value)
+                    .map_err(|_| Error::parse(line, text, "normalizer is not a list of steps"))?;
+                let normalizer = Normalizer::from_names(&names)
+                    .map_err(|e| Error::parse(line, text, e.to_string()))?;
+                self.normalizer = Some(normalizer);
+            }
+            MODEL if self.model.is_none() => {
+                let model = serde_json::from_str(value)
+                    .map_err(|_| Error::parse(line, text, "model is not a JSON object"))
+                    .and_then(|value| {
+                        Model::from_json(&value).map_err(|e| Error::parse(line, text, e.to_string()))
+                    })?;
+                self.model = Some(model);
+            }
+            TOKENIZER if self.tokenizer.is_none() => {
+                let tokenizer = serde_json::from_str(value)
+                    .map_err(|_| Error::parse(line, text, "tokenizer is not a JSON object"))
+                    .and_then(|value| {
+                        SharedTokenizer::from_json(&value).map_err(|e| Error::parse(line, text, e.to_string()))
+                    })?;
+                self.tokenizer = Some(tokenizer);
+            }
+            TEMPLATE if self.template.is_none() => {
+                let template = serde_json::from_str(value)
+                    .map_err(|_| Error::parse(line, text, "template is not a JSON object"))
+                    .and_then(|value| {
+                        Template::from_json(&value).map_err(|e| Error::parse(line, text, e.to_string()))
+                    })?;
+                self.template = Some(template);
+            }
+            NORMALIZER | MODEL | TOKENIZER | TEMPLATE => return Err(Error::parse(line, text, "repeated setting")),
+            _ => return Err(Error::parse(line, text, "unknown setting")),
+        }
+
+        Ok(())
+    }
+
+    /// Read settings saved as `key<TAB>value` lines
+    pub(crate) fn parse_lines(contents: &str) -> Result<Settings> {
+        let mut settings = Settings::default();
+        for (idx, line) in contents.lines().enumerate() {
+            let (key, value) = line.split_once('\t')
+                                   .ok_or_else(|| Error::parse(idx + 1, line, "missing tab"))?;
+            settings.set(idx + 1, line, key, value)?;
+        }
+
+        Ok(settings)
+    }
+
+    /// Get the saved normalizer, or the default one
+    pub(crate) fn normalizer(&self) -> Normalizer {
+        self.normalizer.clone().unwrap_or_default()
+    }
+
+    /// Get the saved model, or the word-level one
+    pub(crate) fn model(&self) -> Model {
+        self.model.clone().unwrap_or_default()
+    }
+
+    /// Get the saved tokenizer, or the default one
+    pub(crate) fn tokenizer(&self) -> SharedTokenizer {
+        self.tokenizer.clone().unwrap_or_default()
+    }
+
+    /// Get the saved template, if there is one
+    pub(crate) fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+
+    /// Give `vocab` the saved options
+    ///
+    /// Fails if the saved template inserts an entry `vocab` lacks.
+    pub(crate) fn apply(self, vocab: Vocab) -> Result<Vocab> {
+        let model = self.model();
+        let mut vocab = vocab.with_normalizer(self.normalizer()).with_model(model);
+        vocab.set_tokenizer(self.tokenizer());
+        match self.template {
+            Some(template) => vocab.with_template(template),
+            None => Ok(vocab),
+        }
+    }
+}
+
+/// Vocabulary entries collected while parsing a saved vocabulary
+#[derive(Debug, Default)]
+pub(crate) struct VocabParts {
+    map: HashMap<String, i32>,
+    ids: HashSet<i32>,
+    counts: HashMap<String, u64>,
+    specials: Vec<String>,
+    unk: Option<String>,
+    /// Options read from the file, if the format has any
+    pub(crate) settings: Settings,
+}
+
+impl VocabParts {
+    /// Add a term, rejecting repeated terms and ids
+    ///
+    /// `line` is the 1-based position of the entry, used in errors.
+    pub(crate) fn insert(&mut self, line: usize, term: String, tok: i32) -> Result<()> {
+        if self.map.contains_key(&term) {
+            return Err(Error::DuplicateTerm { line, term });
+        }
+        if !self.ids.insert(tok) {
+            return Err(Error::DuplicateId { line, id: tok });
+        }
+        self.map.insert(term, tok);
+
+        Ok(())
+    }
+
+    /// Mark a term as special, or as the unknown token
+    ///
+    /// An empty flag leaves the term as a regular entry.
+    pub(crate) fn flag(&mut self, line: usize, text: &str, term: &str, flag: &str) -> Result<()> {
+        match flag {
+            "" => {}
+            SPECIAL_FLAG => self.specials.push(term.to_owned()),
+            UNK_FLAG if self.unk.is_none() => {
+                self.specials.push(term.to_owned());
+                self.unk = Some(term.to_owned());
+            }
+            UNK_FLAG => return Err(Error::parse(line, text, "more than one unk token")),
+            _ => return Err(Error::parse(line, text, "unknown flag")),
+        }
+
+        Ok(())
+    }
+
+    /// Record how often a term was seen while building
+    ///
+    /// Terms never seen, such as most special tokens, are not recorded.
+    pub(crate) fn count(&mut self, term: &str, count: u64) {
+        if count > 0 {
+            self.counts.insert(term.to_owned(), count);
+        }
+    }
+
+    pub(crate) fn finish(self) -> Result<Vocab> {
+        let VocabParts { map, counts, specials, unk, settings, .. } = self;
+        settings.apply(Vocab::from_parts(map, counts, specials, unk.as_deref()))
+    }
+}
+
+/// The flag to save for a vocabulary entry
+pub(crate) fn flag_of(vocab: &Vocab, term: &str, tok: i32) -> &'static str {
+    if vocab.unk_id() == Some(tok) {
+        UNK_FLAG
+    } else if vocab.is_special(term) {
+        SPECIAL_FLAG
+    } else {
+        ""
+    }
+}
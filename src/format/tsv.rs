@@ -0,0 +1,337 @@
+//! Tab separated vocabulary files
+//!
+//! Files start with a header naming the format version and columns,
+//! followed by settings and one row per term. With `→` marking a tab:
+//!
+//! ```text
+//! #tok-vocab 2→term→id→flag→freq
+//! #normalizer→["nfc","lowercase"]
+//! #model→{"type":"word"}
+//! <pad>→0→special→0
+//! <unk>→1→unk→0
+//! the→2→→1042
+//! ```
+//!
+//! `term` and `id` are required; `flag` (empty, `special` or `unk`) and
+//! `freq` are optional. In terms, backslash, tab, newline and carriage
+//! return are written as `\\`, `\t`, `\n` and `\r`, and any other ASCII
+//! control character as `\xHH`.
+//!
+//! Lines starting with `#` hold a setting name and its JSON value, so
+//! a term starting with `#` has it written as `\x23`. The `#template`
+//! setting is only written for vocabularies that have one. Version 1
+//! files have no settings and leave a leading `#` unescaped.
+//!
+//! Files without a header use the legacy layout of a raw term, a tab
+//! and an id, optionally followed by a flag column.
+
+use crate::error::{Error, Result};
+use crate::format::{flag_of, Settings, VocabParts};
+use crate::vocab::Vocab;
+
+/// First word of the header line
+const MAGIC: &str = "#tok-vocab";
+/// Version written by [`render`]
+const VERSION: u32 = 2;
+/// Last version without settings lines
+const VERSION_1: u32 = 1;
+/// Starts a settings line, and must be escaped at the start of a term
+const SETTING: char = '#';
+
+const TERM: &str = "term";
+const ID: &str = "id";
+const FLAG: &str = "flag";
+const FREQ: &str = "freq";
+
+/// Render a vocabulary in the current TSV format
+pub(crate) fn render(vocab: &Vocab) -> String {
+    let freqs = vocab.has_frequencies();
+
+    let mut contents = format!("{} {}\t{}\t{}\t{}", MAGIC, VERSION, TERM, ID, FLAG);
+    if freqs {
+        contents.push('\t');
+        contents.push_str(FREQ);
+    }
+    contents.push('\n');
+
+    for (key, value) in Settings::render(vocab) {
+        contents.push(SETTING);
+        contents.push_str(key);
+        contents.push('\t');
+        contents.push_str(&value);
+        contents.push('\n');
+    }
+
+    for (voc, tok) in vocab.entries() {
+        if voc.starts_with(SETTING) {
+            contents.push_str("\\x23");
+            escape(&voc[1..], &mut contents);
+        } else {
+            escape(voc, &mut contents);
+        }
+        contents.push('\t');
+        contents.push_str(&tok.to_string());
+        contents.push('\t');
+        contents.push_str(flag_of(vocab, voc, tok));
+        if freqs {
+            contents.push('\t');
+            contents.push_str(&vocab.frequency(voc).to_string());
+        }
+        contents.push('\n');
+    }
+
+    contents
+}
+
+/// Parse a TSV vocabulary, in the current or the legacy format
+pub(crate) fn parse(contents: &str) -> Result<Vocab> {
+    match contents.lines().next() {
+        Some(header) if header.starts_with(MAGIC) => parse_versioned(header, contents),
+        _ => parse_legacy(contents),
+    }
+}
+
+/// Parse a file with a `#tok-vocab` header
+fn parse_versioned(header: &str, contents: &str) -> Result<Vocab> {
+    let mut fields = header.split('\t');
+    let version = match fields.next().unwrap_or_default()[MAGIC.len()..].trim().parse::<u32>() {
+        Ok(version) if version == VERSION || version == VERSION_1 => version,
+        _ => return Err(Error::parse(1, header, "unsupported format version")),
+    };
+
+    let columns: Vec<&str> = fields.collect();
+    for (idx, column) in columns.iter().enumerate() {
+        if ![TERM, ID, FLAG, FREQ].contains(column) {
+            return Err(Error::parse(1, header, "unknown column"));
+        }
+        if columns[..idx].contains(column) {
+            return Err(Error::parse(1, header, "repeated column"));
+        }
+    }
+    let column = |name: &str| columns.iter().position(|&c| c == name);
+    let (term_col, id_col) = match (column(TERM), column(ID)) {
+        (Some(term_col), Some(id_col)) => (term_col, id_col),
+        _ => return Err(Error::parse(1, header, "missing term or id column")),
+    };
+    let flag_col = column(FLAG);
+    let freq_col = column(FREQ);
+
+    let mut parts = VocabParts::default();
+    for (idx, line) in contents.lines().enumerate().skip(1) {
+        let lineno = idx + 1;
+        if line.is_empty() {
+            continue;
+        }
+        if version != VERSION_1 && line.starts_with(SETTING) {
+            let (key, value) = line[1..].split_once('\t')
+                                        .ok_or_else(|| Error::parse(lineno, line, "missing tab"))?;
+            parts.settings.set(lineno, line, key, value)?;
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != columns.len() {
+            return Err(Error::parse(lineno, line, "wrong number of columns"));
+        }
+        let voc = unescape(fields[term_col])
+            .ok_or_else(|| Error::parse(lineno, line, "invalid escape"))?;
+        let tok: i32 = fields[id_col]
+            .parse()
+            .map_err(|_| Error::parse(lineno, line, "token is not an integer"))?;
+
+        if let Some(col) = flag_col {
+            parts.flag(lineno, line, &voc, fields[col])?;
+        }
+        if let Some(col) = freq_col {
+            let count = fields[col]
+                .parse()
+                .map_err(|_| Error::parse(lineno, line, "frequency is not an integer"))?;
+            parts.count(&voc, count);
+        }
+        parts.insert(lineno, voc, tok)?;
+    }
+
+    parts.finish()
+}
+
+/// Parse a headerless file of `term<TAB>id[<TAB>flag]` lines
+fn parse_legacy(contents: &str) -> Result<Vocab> {
+    let mut parts = VocabParts::default();
+    for (idx, line) in contents.lines().enumerate() {
+        let lineno = idx + 1;
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut chunks = line.splitn(3, '\t');
+        let voc = chunks.next().unwrap_or_default();
+        let tok: i32 = chunks.next()
+                             .ok_or_else(|| Error::parse(lineno, line, "missing tab"))?
+                             .parse()
+                             .map_err(|_| Error::parse(lineno, line, "token is not an integer"))?;
+
+        parts.insert(lineno, voc.to_owned(), tok)?;
+        parts.flag(lineno, line, voc, chunks.next().unwrap_or_default())?;
+    }
+
+    parts.finish()
+}
+
+/// Append `term` to `out` with TSV escapes applied
+fn escape(term: &str, out: &mut String) {
+    for c in term.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Undo [`escape`], or `None` if `field` has a malformed escape
+fn unescape(field: &str) -> Option<String> {
+    let mut term = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            term.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => term.push('\\'),
+            't' => term.push('\t'),
+            'n' => term.push('\n'),
+            'r' => term.push('\r'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 {
+                    return None;
+                }
+                let byte = u8::from_str_radix(&hex, 16).ok().filter(u8::is_ascii)?;
+                term.push(byte as char);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(term)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use crate::normalizer::{Normalizer, UnicodeForm};
+    use crate::vocab::{PAD, UNK};
+
+    fn vocab_of(terms: &[String]) -> Vocab {
+        let map = terms.iter()
+                       .enumerate()
+                       .map(|(tok, voc)| (voc.to_owned(), tok as i32))
+                       .collect();
+        let counts = terms.iter()
+                          .enumerate()
+                          .map(|(tok, voc)| (voc.to_owned(), tok as u64 * 3 + 1))
+                          .collect();
+        Vocab::from_parts(map, counts, Vec::new(), None)
+    }
+
+    #[test]
+    fn renders_header_flags_and_frequencies() {
+        let mut map = HashMap::new();
+        map.insert(PAD.to_owned(), 0);
+        map.insert(UNK.to_owned(), 1);
+        map.insert("tab\there".to_owned(), 2);
+        let mut counts = HashMap::new();
+        counts.insert("tab\there".to_owned(), 7);
+        let vocab = Vocab::from_parts(map, counts, vec![PAD.to_owned(), UNK.to_owned()], Some(UNK));
+
+        let contents = render(&vocab);
+        assert_eq!(contents, "#tok-vocab 2\tterm\tid\tflag\tfreq\n\
+                              #normalizer\t[\"lowercase\"]\n\
+                              #model\t{\"type\":\"word\"}\n\
+                              <pad>\t0\tspecial\t0\n\
+                              <unk>\t1\tunk\t0\n\
+                              tab\\there\t2\t\t7\n");
+
+        let parsed = parse(&contents).unwrap();
+        assert_eq!(parsed, vocab);
+        assert_eq!(parsed.frequency("tab\there"), 7);
+    }
+
+    #[test]
+    fn reads_legacy_files() {
+        let vocab = parse("<unk>\t0\tunk\nback\\slash\t1\n").unwrap();
+        assert_eq!(vocab.unk_id(), Some(0));
+        assert_eq!(vocab.token_to_id("back\\slash"), Some(1));
+        assert!(!vocab.has_frequencies());
+    }
+
+    #[test]
+    fn settings_lines_and_hash_terms() {
+        let vocab = vocab_of(&["#tag".to_owned(), "a#".to_owned()])
+            .with_normalizer(Normalizer::new().unicode(UnicodeForm::Nfkc));
+        let contents = render(&vocab);
+        assert!(contents.contains("\n#normalizer\t[\"nfkc\"]\n#model\t{\"type\":\"word\"}\n\\x23tag\t0\t\t1\na#\t1"), "{}", contents);
+
+        let parsed = parse(&contents).unwrap();
+        assert_eq!(parsed, vocab);
+        assert_eq!(parsed.normalizer(), vocab.normalizer());
+
+        let old = parse("#tok-vocab 1\tterm\tid\n#tag\t0\n").unwrap();
+        assert_eq!(old.token_to_id("#tag"), Some(0));
+        assert_eq!(old.normalizer(), &Normalizer::default());
+    }
+
+    #[test]
+    fn columns_may_be_reordered_or_omitted() {
+        let vocab = parse("#tok-vocab 1\tid\tterm\n4\ta\\nb\n").unwrap();
+        assert_eq!(vocab.token_to_id("a\nb"), Some(4));
+    }
+
+    #[test]
+    fn rejects_bad_headers_and_rows() {
+        let bad = |contents: &str, line| {
+            matches!(parse(contents), Err(Error::Parse { line: l, .. }) if l == line)
+        };
+        assert!(bad("#tok-vocab 3\tterm\tid\n", 1));
+        assert!(bad("#tok-vocab 2\tterm\tid\n#casing\t[]\n", 2));
+        assert!(bad("#tok-vocab 2\tterm\tid\n#normalizer\t[\"upper\"]\n", 2));
+        assert!(bad("#tok-vocab 2\tterm\tid\n#normalizer\n", 2));
+        assert!(bad("#tok-vocab 1\tterm\tcolour\n", 1));
+        assert!(bad("#tok-vocab 1\tterm\n", 1));
+        assert!(bad("#tok-vocab 1\tterm\tid\tid\n", 1));
+        assert!(bad("#tok-vocab 1\tterm\tid\na\t0\textra\n", 2));
+        assert!(bad("#tok-vocab 1\tterm\tid\na\\q\t0\n", 2));
+        assert!(bad("#tok-vocab 1\tterm\tid\tfreq\na\t0\tmany\n", 2));
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        let mut out = String::new();
+        escape("a\\b\tc\u{1}\u{7f}é", &mut out);
+        assert_eq!(out, "a\\\\b\\tc\\x01\\x7fé");
+        assert_eq!(unescape(&out).as_deref(), Some("a\\b\tc\u{1}\u{7f}é"));
+        assert_eq!(unescape("\\x8f"), None);
+        assert_eq!(unescape("\\x4"), None);
+        assert_eq!(unescape("trailing\\"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn any_terms_round_trip(terms in prop::collection::hash_set("(?s).*", 0..20)) {
+            let terms: Vec<String> = terms.into_iter().collect();
+            let vocab = vocab_of(&terms);
+            let parsed = parse(&render(&vocab)).unwrap();
+            prop_assert_eq!(&parsed, &vocab);
+            prop_assert_eq!(parsed.most_common(terms.len()), vocab.most_common(terms.len()));
+        }
+    }
+}
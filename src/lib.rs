@@ -1 +1,43 @@
-pub mod vocab;
\ No newline at end of file
+//! Vocabulary building with Rust.
+//!
+//! The core API lives in plain Rust so it can be used as an `rlib`
+//! from other crates. The Python extension module is a thin wrapper
+//! over the same types and is only compiled with the `python` feature.
+//!
+//! ```no_run
+//! use tok::Vocab;
+//!
+//! let vocab = Vocab::new("corpus.txt")?;
+//! println!("{} terms", vocab.size());
+//! # Ok::<(), tok::Error>(())
+//! ```
+
+mod builder;
+mod count;
+mod encoding;
+mod error;
+mod format;
+mod mapped;
+mod model;
+mod normalizer;
+mod template;
+mod tokenizer;
+mod vocab;
+
+#[cfg(feature = "python")]
+mod python;
+
+pub use crate::builder::{IdOrder, VocabBuilder};
+pub use crate::encoding::{Encoding, Padding, PaddingLength, PaddingSide, Truncation, TruncationStrategy};
+pub use crate::error::{Error, Result};
+pub use crate::mapped::MappedVocab;
+pub use crate::model::{
+    Bpe, BpeTrainer, CharNgrams, Model, Unigram, UnigramTrainer, WordPiece, WordPieceTrainer,
+};
+pub use crate::normalizer::{Normalizer, NormalizerStep, UnicodeForm};
+pub use crate::template::Template;
+pub use crate::tokenizer::{
+    DefaultTokenizer, PunctuationTokenizer, RegexTokenizer, SplitDigits, Tokenizer, WhitespaceTokenizer,
+    WordTokenizer,
+};
+pub use crate::vocab::{Vocab, BOS, EOS, MASK, PAD, UNK};
@@ -0,0 +1,303 @@
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::encoding::{self, Encoder, Encoding, Truncation};
+use crate::error::{Error, Result};
+use crate::format::binary::{self, Layout};
+use crate::model::Model;
+use crate::normalizer::Normalizer;
+use crate::template::Template;
+use crate::tokenizer::{SharedTokenizer, Tokenizer};
+use crate::vocab::Vocab;
+
+/// A binary vocabulary queried in place through a memory map
+///
+/// Opening a file only checks its header, after which lookups read straight
+/// from the mapped pages without building a `HashMap` or allocating a
+/// `String` per term. Processes that map the same file share a single
+/// copy of it in the page cache.
+///
+/// Files are written with [`Vocab::write_binary`].
+///
+/// ```no_run
+/// use tok::MappedVocab;
+///
+/// let vocab = MappedVocab::open("vocab.bin")?;
+/// let ids = vocab.encode("the cat sat");
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct MappedVocab {
+    map: Mmap,
+    layout: Layout,
+    normalizer: Normalizer,
+    tokenizer: SharedTokenizer,
+    model: Model,
+    template: Option<Template>,
+}
+
+impl MappedVocab {
+    /// Map a binary vocabulary file
+    ///
+    /// Only the header and the bounds of each section are checked, so
+    /// opening takes the same time whatever the size of the file. Lookups
+    /// never go out of bounds, but they may return wrong results for a
+    /// damaged file until [`MappedVocab::verify`] has succeeded.
+    ///
+    /// The file must not be modified while it is mapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a file written by [`Vocab::write_binary`]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        // SAFETY: the mapping is read-only, and callers are told not to
+        // modify the file while it is open.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| Error::io(path, e))?;
+        let layout = Layout::parse(&map)?;
+        let settings = layout.settings(&map)?;
+        if let Some(template) = settings.template() {
+            template.check(|token| layout.find_term(&map, token).is_some())?;
+        }
+
+        Ok(MappedVocab {
+            normalizer: settings.normalizer(),
+            model: settings.model(),
+            template: settings.template().cloned(),
+            tokenizer: settings.tokenizer(),
+            map,
+            layout,
+        })
+    }
+
+    /// Check the checksum and every entry of the mapped file
+    ///
+    /// This reads the whole file, so it takes time in proportion to its
+    /// size.
+    pub fn verify(&self) -> Result<()> {
+        self.layout.verify(&self.map)
+    }
+
+    /// Replace the tokenizer used by [`MappedVocab::encode`]
+    ///
+    /// See [`Vocab::with_tokenizer`].
+    pub fn with_tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = SharedTokenizer::new(tokenizer);
+        self
+    }
+
+    /// Get the normalizer saved with the vocabulary
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
+    /// Get the model saved with the vocabulary
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Get the template saved with the vocabulary, if there is one
+    pub fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+
+    /// Get the number of vocabulary terms
+    pub fn size(&self) -> usize {
+        self.layout.count
+    }
+
+    /// Look up the token id of a vocabulary term
+    pub fn token_to_id(&self, term: &str) -> Option<i32> {
+        self.layout.find_term(&self.map, term)
+                   .map(|idx| self.layout.entry(&self.map, idx).id)
+    }
+
+    /// Look up the vocabulary term for a token id
+    pub fn id_to_token(&self, id: i32) -> Option<&str> {
+        self.layout.find_id(&self.map, id)
+                   .map(|idx| self.layout.entry(&self.map, idx).term)
+    }
+
+    /// Check whether a term is a reserved special token
+    pub fn is_special(&self, term: &str) -> bool {
+        self.layout.find_term(&self.map, term)
+                   .is_some_and(|idx| self.layout.entry(&self.map, idx).special)
+    }
+
+    /// Get the id used for out-of-vocabulary terms, if any
+    pub fn unk_id(&self) -> Option<i32> {
+        self.layout.unk
+    }
+
+    /// Encode raw text as token ids
+    ///
+    /// Behaves like [`Vocab::encode`].
+    pub fn encode(&self, text: &str) -> Vec<i32> {
+        self.encode_tokens(&self.terms(text))
+    }
+
+    /// Encode raw text in the `n` most probable ways, best first
+    ///
+    /// Behaves like [`Vocab::encode_nbest`].
+    pub fn encode_nbest(&self, text: &str, n: usize) -> Vec<Vec<i32>> {
+        self.model.encode_nbest(&self.terms(text), n, |piece| self.token_to_id(piece), self.layout.unk)
+    }
+
+    /// Encode raw text with a randomly drawn split of each term
+    ///
+    /// Behaves like [`Vocab::encode_sample`].
+    pub fn encode_sample(&self, text: &str, alpha: f64, seed: u64) -> Vec<i32> {
+        self.model.encode_sample(&self.terms(text), alpha, seed, |piece| self.token_to_id(piece), self.layout.unk)
+    }
+
+    /// Encode raw text, keeping the span of text behind every token
+    ///
+    /// Behaves like [`Vocab::encode_with_offsets`], except that
+    /// truncation is not saved with the file, so nothing is cut.
+    pub fn encode_with_offsets(&self, text: &str) -> Result<Encoding> {
+        encoding::encode(self, text, None)
+    }
+
+    /// Encode a pair of texts
+    ///
+    /// Behaves like [`Vocab::encode_pair`], without truncation.
+    pub fn encode_pair(&self, first: &str, second: &str) -> Result<Encoding> {
+        encoding::encode(self, first, Some(second))
+    }
+
+    /// Normalize and split text into terms
+    fn terms(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(&self.normalizer.normalize(text))
+    }
+
+    /// Encode already tokenized terms as token ids
+    ///
+    /// Behaves like [`Vocab::encode_tokens`].
+    pub fn encode_tokens(&self, tokens: &[String]) -> Vec<i32> {
+        self.model.encode_terms(tokens, |piece| self.token_to_id(piece), self.layout.unk)
+    }
+
+    /// Decode token ids back into vocabulary terms
+    ///
+    /// Behaves like [`Vocab::decode`].
+    pub fn decode(&self, ids: &[i32]) -> Vec<String> {
+        ids.iter()
+           .filter_map(|&id| self.id_to_token(id))
+           .map(|s| s.to_owned())
+           .collect()
+    }
+
+    /// Decode token ids back into the bytes they stand for
+    ///
+    /// Behaves like [`Vocab::decode_bytes`].
+    pub fn decode_bytes(&self, ids: &[i32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for term in ids.iter().filter_map(|&id| self.id_to_token(id)) {
+            if self.is_special(term) {
+                bytes.extend_from_slice(term.as_bytes());
+            } else {
+                self.model.piece_bytes(term, &mut bytes);
+            }
+        }
+
+        bytes
+    }
+
+    /// Copy the whole vocabulary into an owned [`Vocab`]
+    pub fn to_vocab(&self) -> Result<Vocab> {
+        binary::parse(&self.map)
+    }
+}
+
+impl Encoder for MappedVocab {
+    fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        &*self.tokenizer
+    }
+
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    fn unk_id(&self) -> Option<i32> {
+        self.layout.unk
+    }
+
+    fn token_to_id(&self, term: &str) -> Option<i32> {
+        MappedVocab::token_to_id(self, term)
+    }
+
+    fn id_to_token(&self, id: i32) -> Option<&str> {
+        MappedVocab::id_to_token(self, id)
+    }
+
+    fn is_special(&self, term: &str) -> bool {
+        MappedVocab::is_special(self, term)
+    }
+
+    fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+
+    fn truncation(&self) -> Option<&Truncation> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vocab::UNK;
+
+    #[test]
+    fn matches_the_vocab_it_was_written_from() {
+        let vocab = Vocab::builder().unk_token(UNK)
+                                    .build_reader("It's a mapped, mapped file".as_bytes())
+                                    .unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        vocab.write_binary(file.path().to_str().unwrap()).unwrap();
+
+        let mapped = MappedVocab::open(file.path()).unwrap();
+        assert_eq!(mapped.size(), vocab.size());
+        assert_eq!(mapped.unk_id(), vocab.unk_id());
+        assert!(mapped.is_special(UNK));
+        for text in &["it's a file", "an unmapped FILE"] {
+            assert_eq!(mapped.encode(text), vocab.encode(text));
+        }
+        assert_eq!(mapped.decode(&[3, 0, 42]), vocab.decode(&[3, 0, 42]));
+        assert_eq!(mapped.encode_with_offsets("An unmapped FILE").unwrap(), vocab.encode_with_offsets("An unmapped FILE").unwrap());
+        assert_eq!(mapped.to_vocab().unwrap(), vocab);
+    }
+
+    #[test]
+    fn rejects_text_files() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "the\t0\n").unwrap();
+        assert!(matches!(MappedVocab::open(file.path()), Err(Error::Corrupt { .. })));
+    }
+
+    #[test]
+    fn verify_finds_damaged_entries() {
+        let vocab = Vocab::builder().build_reader("a damaged file".as_bytes()).unwrap();
+        let mut bytes = vocab.to_binary();
+        // Point the first entry, just after the 40-byte header, past the arena.
+        bytes[56..64].copy_from_slice(&u64::MAX.to_le_bytes());
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &bytes).unwrap();
+
+        let mapped = MappedVocab::open(file.path()).unwrap();
+        assert!(matches!(mapped.verify(), Err(Error::Corrupt { .. })));
+        assert_eq!(mapped.id_to_token(0), Some(""));
+        assert_eq!(mapped.token_to_id("file"), vocab.token_to_id("file"));
+
+        std::fs::write(file.path(), vocab.to_binary()).unwrap();
+        assert!(MappedVocab::open(file.path()).unwrap().verify().is_ok());
+    }
+}
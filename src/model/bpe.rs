@@ -0,0 +1,639 @@
+//! Byte-pair encoding
+//!
+//! Training starts from every term split into characters and
+//! repeatedly merges the most frequent pair of adjacent symbols,
+//! weighting each term by how often it was seen. Encoding replays the
+//! learned merges on a term, earliest merge first.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+use crate::model::{byte_level, config, training_words, Model};
+use crate::vocab::Vocab;
+
+/// Byte-pair encoding merges, in the order they were learned
+///
+/// Built by [`BpeTrainer::train`], or from merges learned elsewhere.
+///
+/// ```
+/// use tok::Bpe;
+///
+/// let merges = vec![("l".to_owned(), "o".to_owned()), ("lo".to_owned(), "w".to_owned())];
+/// let bpe = Bpe::new(merges, None);
+/// assert_eq!(bpe.pieces("lower"), vec!["low", "e", "r"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bpe {
+    merges: Vec<(String, String)>,
+    /// Appended to the last character of every term
+    suffix: Option<String>,
+    /// Prepended to every character that continues a term
+    prefix: Option<String>,
+    /// Whether terms are split into bytes rather than characters
+    byte_level: bool,
+    /// Rank of each merge, by left then right symbol
+    ranks: HashMap<String, HashMap<String, usize>>,
+}
+
+impl PartialEq for Bpe {
+    fn eq(&self, other: &Bpe) -> bool {
+        self.merges == other.merges
+            && self.suffix == other.suffix
+            && self.prefix == other.prefix
+            && self.byte_level == other.byte_level
+    }
+}
+
+impl Bpe {
+    /// Create a model from merges, earliest first
+    ///
+    /// # Arguments
+    ///
+    /// * `merges` - pairs of adjacent symbols to join, in priority order
+    /// * `suffix` - end-of-word marker appended to the last character
+    ///   of every term, such as `</w>`
+    pub fn new(merges: Vec<(String, String)>, suffix: Option<String>) -> Self {
+        let mut ranks: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        for (rank, (left, right)) in merges.iter().enumerate() {
+            ranks.entry(left.to_owned())
+                 .or_default()
+                 .entry(right.to_owned())
+                 .or_insert(rank);
+        }
+
+        Bpe { merges, suffix, prefix: None, byte_level: false, ranks }
+    }
+
+    /// Read merges from a GPT-2 style `merges.txt`
+    ///
+    /// Each line holds the two symbols of a merge separated by a space,
+    /// earliest first. A leading `#version` line and blank lines are
+    /// skipped.
+    pub fn parse_merges(contents: &str) -> Result<Vec<(String, String)>> {
+        let mut merges = Vec::new();
+        for (idx, line) in contents.lines().enumerate() {
+            if line.is_empty() || (idx == 0 && line.starts_with("#version")) {
+                continue;
+            }
+            let (left, right) = line.split_once(' ')
+                                    .filter(|(left, right)| !left.is_empty() && !right.is_empty())
+                                    .ok_or_else(|| Error::parse(idx + 1, line, "expected two symbols"))?;
+            merges.push((left.to_owned(), right.to_owned()));
+        }
+
+        Ok(merges)
+    }
+
+    /// Split terms into UTF-8 bytes instead of characters
+    ///
+    /// Each byte is spelled with the character GPT-2 uses for it, so
+    /// every input can be encoded from the 256 byte symbols, and
+    /// [`Vocab::decode_bytes`] gives back the exact bytes.
+    pub fn with_byte_level(mut self, byte_level: bool) -> Self {
+        self.byte_level = byte_level;
+        self
+    }
+
+    /// Whether terms are split into bytes rather than characters
+    pub fn is_byte_level(&self) -> bool {
+        self.byte_level
+    }
+
+    /// Mark every character that continues a term with `prefix`, such as `##`
+    ///
+    /// Merges drop the prefix of their right symbol, so `un` and `##able`
+    /// join into `unable`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_owned());
+        self
+    }
+
+    /// Get the continuation prefix, if any
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Get the merges, earliest first
+    pub fn merges(&self) -> &[(String, String)] {
+        &self.merges
+    }
+
+    /// Get the end-of-word marker, if any
+    pub fn suffix(&self) -> Option<&str> {
+        self.suffix.as_deref()
+    }
+
+    /// Split a term into subword pieces
+    ///
+    /// The term starts as single characters, and the adjacent pair with
+    /// the earliest merge is joined everywhere until no pair has one.
+    pub fn pieces(&self, term: &str) -> Vec<String> {
+        let mut pieces = self.symbols(term);
+        loop {
+            let best = pieces.windows(2)
+                             .filter_map(|pair| self.rank(&pair[0], &pair[1]))
+                             .min();
+            let (left, right) = match best {
+                Some(rank) => &self.merges[rank],
+                None => return pieces,
+            };
+
+            let mut merged = Vec::with_capacity(pieces.len());
+            let mut rest = pieces.into_iter().peekable();
+            while let Some(piece) = rest.next() {
+                if piece == *left && rest.peek() == Some(right) {
+                    rest.next();
+                    merged.push(self.join(&piece, right));
+                } else {
+                    merged.push(piece);
+                }
+            }
+            pieces = merged;
+        }
+    }
+
+    /// Split a term into subword pieces with the byte span of the term each covers
+    pub(crate) fn piece_spans(&self, term: &str) -> Vec<(String, (usize, usize))> {
+        let bounds: Vec<usize> = if self.byte_level {
+            (0..=term.len()).collect()
+        } else {
+            term.char_indices().map(|(at, _)| at).chain(Some(term.len())).collect()
+        };
+
+        let mut start = 0;
+        self.pieces(term)
+            .into_iter()
+            .map(|piece| {
+                let end = (start + self.symbol_count(&piece)).min(bounds.len() - 1);
+                let span = (bounds[start], bounds[end]);
+                start = end;
+                (piece, span)
+            })
+            .collect()
+    }
+
+    /// Count the characters or bytes of a term that a piece spells
+    fn symbol_count(&self, piece: &str) -> usize {
+        let piece = self.suffix
+                        .as_deref()
+                        .and_then(|suffix| piece.strip_suffix(suffix))
+                        .unwrap_or(piece);
+        let piece = self.prefix
+                        .as_deref()
+                        .and_then(|prefix| piece.strip_prefix(prefix))
+                        .unwrap_or(piece);
+
+        piece.chars().count()
+    }
+
+    /// Split a term into characters or bytes, with the suffix on the last one
+    fn symbols(&self, term: &str) -> Vec<String> {
+        let mut symbols: Vec<String> = if self.byte_level {
+            byte_level::encode(term).map(String::from).collect()
+        } else {
+            term.chars().map(String::from).collect()
+        };
+        if let (Some(last), Some(suffix)) = (symbols.last_mut(), &self.suffix) {
+            last.push_str(suffix);
+        }
+        if let Some(prefix) = &self.prefix {
+            for symbol in symbols.iter_mut().skip(1) {
+                symbol.insert_str(0, prefix);
+            }
+        }
+
+        symbols
+    }
+
+    /// Join two adjacent symbols into one
+    fn join(&self, left: &str, right: &str) -> String {
+        let right = self.prefix
+                        .as_deref()
+                        .and_then(|prefix| right.strip_prefix(prefix))
+                        .unwrap_or(right);
+
+        format!("{}{}", left, right)
+    }
+
+    fn with_merges(self, merges: Vec<(String, String)>) -> Self {
+        Bpe { prefix: self.prefix, ..Bpe::new(merges, self.suffix) }.with_byte_level(self.byte_level)
+    }
+
+    fn rank(&self, left: &str, right: &str) -> Option<usize> {
+        self.ranks.get(left)?.get(right).copied()
+    }
+
+    /// Append the bytes a piece stands for to `bytes`
+    pub(crate) fn piece_bytes(&self, piece: &str, bytes: &mut Vec<u8>) {
+        if !self.byte_level {
+            return bytes.extend_from_slice(piece.as_bytes());
+        }
+        let piece = self.suffix
+                        .as_deref()
+                        .and_then(|suffix| piece.strip_suffix(suffix))
+                        .unwrap_or(piece);
+        let piece = self.prefix
+                        .as_deref()
+                        .and_then(|prefix| piece.strip_prefix(prefix))
+                        .unwrap_or(piece);
+        for c in piece.chars() {
+            match byte_level::to_byte(c) {
+                Some(byte) => bytes.push(byte),
+                None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "type": "bpe",
+            "suffix": self.suffix,
+            "prefix": self.prefix,
+            "byte_level": self.byte_level,
+            "merges": self.merges,
+        })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Bpe> {
+        let affix = |key| match value.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(affix)) => Ok(Some(affix.to_owned())),
+            Some(_) => Err(config(format!("BPE {} is not a string", key))),
+        };
+        let suffix = affix("suffix")?;
+        let prefix = affix("prefix")?;
+        let merges = value.get("merges")
+                          .and_then(|merges| serde_json::from_value(merges.clone()).ok())
+                          .ok_or_else(|| config("BPE merges are not a list of pairs"))?;
+        let byte_level = match value.get("byte_level") {
+            None => false,
+            Some(Value::Bool(byte_level)) => *byte_level,
+            Some(_) => return Err(config("BPE byte_level is not a boolean")),
+        };
+
+        let bpe = Bpe::new(merges, suffix).with_byte_level(byte_level);
+
+        Ok(Bpe { prefix, ..bpe })
+    }
+}
+
+/// Options for learning byte-pair encoding merges
+///
+/// Merges are learned from the term frequencies a word-level
+/// vocabulary gathered while it was built, so training never reads
+/// the corpus again.
+///
+/// ```no_run
+/// use tok::{BpeTrainer, Vocab};
+///
+/// let words = Vocab::new("corpus.txt")?;
+/// let subwords = BpeTrainer::new(8_000).train(&words)?;
+/// let ids = subwords.encode("unseen words still encode");
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct BpeTrainer {
+    /// Target number of entries, special tokens included
+    vocab_size: usize,
+    /// Pairs seen fewer times than this are never merged
+    min_frequency: u64,
+    /// End-of-word marker appended to the last character of each term
+    suffix: Option<String>,
+    /// Prepended to every character that continues a term
+    prefix: Option<String>,
+    /// Whether terms are split into bytes rather than characters
+    byte_level: bool,
+}
+
+impl BpeTrainer {
+    /// Learn merges until the vocabulary holds `vocab_size` entries
+    ///
+    /// The vocabulary never has fewer entries than the special tokens
+    /// and every character of the training terms.
+    pub fn new(vocab_size: usize) -> Self {
+        BpeTrainer { vocab_size, min_frequency: 2, suffix: None, prefix: None, byte_level: false }
+    }
+
+    /// Stop once the most frequent pair is seen fewer than `min_frequency` times
+    ///
+    /// Defaults to 2, so no merge is learned from a single occurrence.
+    pub fn min_frequency(mut self, min_frequency: u64) -> Self {
+        self.min_frequency = min_frequency;
+        self
+    }
+
+    /// Mark the end of every term with `suffix`, such as `</w>`
+    ///
+    /// Pieces that end a word are then distinct from the same letters
+    /// inside a word.
+    pub fn suffix(mut self, suffix: &str) -> Self {
+        self.suffix = Some(suffix.to_owned());
+        self
+    }
+
+    /// Mark every character that continues a term with `prefix`, such as `##`
+    ///
+    /// Pieces from inside a word are then distinct from the same letters
+    /// starting a word.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_owned());
+        self
+    }
+
+    /// Learn merges over UTF-8 bytes instead of characters
+    ///
+    /// The vocabulary starts with all 256 byte symbols, so any text can
+    /// be encoded without the unknown token. Pair with
+    /// [`RegexTokenizer::gpt2`](crate::RegexTokenizer::gpt2) and
+    /// [`Normalizer::new`](crate::Normalizer::new) to keep whitespace and
+    /// case, so [`Vocab::decode_bytes`] returns the original text.
+    pub fn byte_level(mut self) -> Self {
+        self.byte_level = true;
+        self
+    }
+
+    /// Learn merges from the term frequencies of a word-level vocabulary
+    ///
+    /// The result keeps the special tokens, normalizer and tokenizer of
+    /// `vocab`. Its frequencies count each piece in the segmented
+    /// training terms.
+    ///
+    /// # Arguments
+    ///
+    /// * `vocab` - vocabulary built from a corpus, with frequencies
+    pub fn train(&self, vocab: &Vocab) -> Result<Vocab> {
+        let words = training_words(vocab)?;
+        let model = Bpe { prefix: self.prefix.clone(), ..Bpe::new(Vec::new(), self.suffix.clone()) }
+            .with_byte_level(self.byte_level);
+
+        let mut symbols = Symbols::default();
+        if self.byte_level {
+            for byte in 0..=255 {
+                symbols.intern(&byte_level::to_char(byte).to_string());
+            }
+        }
+        let mut split: Vec<Vec<String>> = words.iter().map(|(term, _)| model.symbols(term)).collect();
+        let mut alphabet: Vec<&String> = split.iter().flatten().collect();
+        alphabet.sort_unstable();
+        alphabet.dedup();
+        for symbol in alphabet {
+            symbols.intern(symbol);
+        }
+        let mut seqs: Vec<Vec<u32>> = split.iter_mut()
+                                           .map(|pieces| pieces.iter().map(|p| symbols.intern(p)).collect())
+                                           .collect();
+
+        let mut pairs: HashMap<(u32, u32), u64> = HashMap::new();
+        let mut places: HashMap<(u32, u32), HashSet<usize>> = HashMap::new();
+        for (word, seq) in seqs.iter().enumerate() {
+            for pair in seq.windows(2) {
+                *pairs.entry((pair[0], pair[1])).or_default() += words[word].1;
+                places.entry((pair[0], pair[1])).or_default().insert(word);
+            }
+        }
+        // Most frequent first, ties going to the pair of lowest ids. Stale
+        // entries are skipped when popped.
+        let mut queue: BinaryHeap<(u64, Reverse<(u32, u32)>)> = pairs.iter()
+                                                                     .map(|(&pair, &count)| (count, Reverse(pair)))
+                                                                     .collect();
+
+        let budget = self.vocab_size.saturating_sub(vocab.special_tokens().len());
+        let mut merges = Vec::new();
+        while symbols.len() < budget {
+            let (count, Reverse(pair)) = match queue.pop() {
+                Some(top) => top,
+                None => break,
+            };
+            let current = pairs.get(&pair).copied().unwrap_or(0);
+            if current != count {
+                if current > 0 {
+                    queue.push((current, Reverse(pair)));
+                }
+                continue;
+            }
+            if count < self.min_frequency {
+                break;
+            }
+
+            let (left, right) = (symbols.name(pair.0).to_owned(), symbols.name(pair.1).to_owned());
+            let joined = symbols.intern(&model.join(&left, &right));
+            merges.push((left, right));
+
+            let mut touched = HashSet::new();
+            for word in places.remove(&pair).unwrap_or_default() {
+                let seq = &mut seqs[word];
+                let count = words[word].1;
+                for old in seq.windows(2) {
+                    let old = (old[0], old[1]);
+                    if let Some(total) = pairs.get_mut(&old) {
+                        *total -= count;
+                    }
+                    touched.insert(old);
+                }
+                merge(seq, pair, joined);
+                for new in seq.windows(2) {
+                    let new = (new[0], new[1]);
+                    *pairs.entry(new).or_default() += count;
+                    places.entry(new).or_default().insert(word);
+                    touched.insert(new);
+                }
+            }
+            for pair in touched {
+                match pairs.get(&pair).copied() {
+                    Some(0) | None => {
+                        pairs.remove(&pair);
+                    }
+                    Some(count) => queue.push((count, Reverse(pair))),
+                }
+            }
+        }
+
+        let mut counts = HashMap::new();
+        for (seq, (_, count)) in seqs.iter().zip(&words) {
+            for &symbol in seq {
+                *counts.entry(symbols.name(symbol).to_owned()).or_insert(0) += count;
+            }
+        }
+
+        Ok(vocab.derive(symbols.names, counts, Model::Bpe(model.with_merges(merges))))
+    }
+}
+
+/// Join every non-overlapping occurrence of `pair` in `seq`, left to right
+fn merge(seq: &mut Vec<u32>, pair: (u32, u32), joined: u32) {
+    let mut read = 0;
+    let mut write = 0;
+    while read < seq.len() {
+        if read + 1 < seq.len() && (seq[read], seq[read + 1]) == pair {
+            seq[write] = joined;
+            read += 2;
+        } else {
+            seq[write] = seq[read];
+            read += 1;
+        }
+        write += 1;
+    }
+    seq.truncate(write);
+}
+
+/// Interned symbol strings, numbered in the order first seen
+#[derive(Debug, Default)]
+struct Symbols {
+    names: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+impl Symbols {
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.names.push(name.to_owned());
+        self.ids.insert(name.to_owned(), id);
+
+        id
+    }
+
+    fn name(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    fn len(&self) -> usize {
+        self.names.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::normalizer::Normalizer;
+    use crate::tokenizer::RegexTokenizer;
+    use crate::vocab::UNK;
+
+    fn words(text: &str) -> Vocab {
+        Vocab::builder().unk_token(UNK).build_reader(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn learns_the_most_frequent_pairs_first() {
+        let vocab = BpeTrainer::new(1 + 7 + 2).train(&words("low low low lower lowest")).unwrap();
+        let bpe = match vocab.model() {
+            Model::Bpe(bpe) => bpe.clone(),
+            other => panic!("expected a BPE model, got {:?}", other),
+        };
+        assert_eq!(bpe.merges(), &[("l".to_owned(), "o".to_owned()), ("lo".to_owned(), "w".to_owned())]);
+        assert_eq!(vocab.entries(), vec![(UNK, 0), ("e", 1), ("l", 2), ("o", 3), ("r", 4), ("s", 5),
+                                         ("t", 6), ("w", 7), ("lo", 8), ("low", 9)]);
+        assert_eq!(vocab.frequency("low"), 5);
+        assert_eq!(vocab.frequency("l"), 0);
+    }
+
+    #[test]
+    fn segments_unseen_words() {
+        let vocab = BpeTrainer::new(100).train(&words("slow slower lowest newer newest")).unwrap();
+        let pieces = vocab.decode(&vocab.encode("slowest"));
+        assert_eq!(pieces.concat(), "slowest");
+        assert!(pieces.len() < "slowest".len(), "{:?}", pieces);
+        assert_eq!(vocab.encode("zzz"), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn suffix_marks_word_ends() {
+        let vocab = BpeTrainer::new(100).suffix("</w>")
+                                        .train(&words("aa aa ab ab ba"))
+                                        .unwrap();
+        assert_eq!(vocab.decode(&vocab.encode("aa ab")), vec!["aa</w>", "ab</w>"]);
+        assert_eq!(vocab.token_to_id("a</w>"), Some(2));
+        assert_eq!(vocab.decode(&vocab.encode("aab")), vec!["a", "ab</w>"]);
+    }
+
+    #[test]
+    fn stops_at_min_frequency_and_size() {
+        let vocab = words("abc abc abd");
+        let single = BpeTrainer::new(100).min_frequency(4).train(&vocab).unwrap();
+        assert_eq!(single.size(), 1 + 4);
+        let capped = BpeTrainer::new(1 + 4 + 1).train(&vocab).unwrap();
+        assert_eq!(capped.token_to_id("ab"), Some(5));
+        assert_eq!(capped.size(), 6);
+    }
+
+    #[test]
+    fn overlapping_pairs_merge_left_to_right() {
+        let mut seq = vec![1, 1, 1, 2, 1, 1];
+        merge(&mut seq, (1, 1), 9);
+        assert_eq!(seq, vec![9, 1, 2, 9]);
+        assert_eq!(Bpe::new(vec![("a".into(), "a".into())], None).pieces("aaa"), vec!["aa", "a"]);
+    }
+
+    #[test]
+    fn model_is_saved_with_the_vocabulary() {
+        let vocab = BpeTrainer::new(100).suffix("</w>")
+                                        .train(&words("low lower lowest slow"))
+                                        .unwrap();
+        let ids = vocab.encode("slower");
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        vocab.write(path).unwrap();
+        let loaded = Vocab::load(path).unwrap();
+        assert_eq!(loaded, vocab);
+        assert_eq!(loaded.encode("slower"), ids);
+
+        vocab.write_binary(path).unwrap();
+        assert_eq!(Vocab::load_binary(path).unwrap(), vocab);
+        let mapped = crate::MappedVocab::open(path).unwrap();
+        assert_eq!(mapped.model(), vocab.model());
+        assert_eq!(mapped.encode("slower"), ids);
+    }
+
+    #[test]
+    fn byte_level_encodes_anything_losslessly() {
+        let corpus = "the cat sat on the mat\nthe hat";
+        let text = "The 🐈 sat\ton\u{0}the\u{ad}mat!";
+        let words = Vocab::builder().normalizer(Normalizer::new())
+                                    .tokenizer(RegexTokenizer::gpt2())
+                                    .unk_token(UNK)
+                                    .build_reader(corpus.as_bytes())
+                                    .unwrap();
+        let vocab = BpeTrainer::new(1 + 256 + 8).byte_level()
+                                                    .min_frequency(1)
+                                                    .train(&words).unwrap();
+        assert_eq!(vocab.size(), 1 + 256 + 8);
+        assert_eq!(vocab.token_to_id("Ġ"), Some(1 + 32));
+        assert_eq!(vocab.token_to_id("the"), Some(259));
+
+        let ids = vocab.encode(text);
+        assert!(!ids.contains(&0));
+        assert_eq!(vocab.decode_bytes(&ids), text.as_bytes());
+        assert_eq!(vocab.decode_bytes(&[0, vocab.token_to_id("Ġs").unwrap()]), b"<unk> s");
+    }
+
+    #[test]
+    fn reads_gpt2_merges() {
+        let merges = Bpe::parse_merges("#version: 0.2\nh e\nhe l\nhel l\nhell o\n\nĠ w\n").unwrap();
+        assert_eq!(merges.len(), 5);
+        assert_eq!(merges[4], ("Ġ".to_owned(), "w".to_owned()));
+        assert!(matches!(Bpe::parse_merges("h e\nhe\n"), Err(Error::Parse { line: 2, .. })));
+
+        let vocab = Vocab::from_json(r#"{"Ġ":0,"h":1,"e":2,"l":3,"o":4,"w":5,"he":6,"hel":7,"hell":8,"hello":9,"Ġw":10}"#)
+            .unwrap()
+            .with_normalizer(Normalizer::new())
+            .with_tokenizer(RegexTokenizer::gpt2())
+            .with_model(Model::Bpe(Bpe::new(merges, None).with_byte_level(true)));
+        assert_eq!(vocab.encode("hello who"), vec![9, 10, 1, 4]);
+        assert_eq!(vocab.decode_bytes(&[9, 10, 1, 4]), b"hello who");
+    }
+
+    #[test]
+    fn requires_word_frequencies() {
+        let loaded = Vocab::from_json(r#"{"a": 0}"#).unwrap();
+        assert!(matches!(BpeTrainer::new(10).train(&loaded), Err(crate::Error::Config { .. })));
+        let trained = BpeTrainer::new(10).train(&words("ab ab")).unwrap();
+        assert!(matches!(BpeTrainer::new(10).train(&trained), Err(crate::Error::Config { .. })));
+    }
+}
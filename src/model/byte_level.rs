@@ -0,0 +1,71 @@
+//! GPT-2 byte-to-unicode mapping
+//!
+//! Byte-level models work on the UTF-8 bytes of a term, each shown as
+//! one printable character so pieces stay valid strings. Printable
+//! Latin-1 bytes stand for themselves; whitespace, control bytes and
+//! the soft hyphen are shifted past U+00FF in byte order, so a space
+//! is `Ġ` and a newline `Ċ`, matching GPT-2 vocabularies.
+
+/// Bytes shown as themselves
+fn is_printable(byte: u32) -> bool {
+    matches!(byte, 0x21..=0x7e | 0xa1..=0xac | 0xae..=0xff)
+}
+
+/// Get the character standing for `byte`
+pub(crate) fn to_char(byte: u8) -> char {
+    let shifted = match byte {
+        _ if is_printable(byte.into()) => return char::from(byte),
+        0x00..=0x20 => byte as u32,
+        0x7f..=0xa0 => byte as u32 - 0x7f + 0x21,
+        _ => 0x21 + 0x22,
+    };
+
+    char::from_u32(0x100 + shifted).unwrap_or_default()
+}
+
+/// Get the byte `c` stands for, if it is one of the mapped characters
+pub(crate) fn to_byte(c: char) -> Option<u8> {
+    let byte = match c as u32 {
+        code if is_printable(code) => code,
+        code @ 0x100..=0x120 => code - 0x100,
+        code @ 0x121..=0x142 => code - 0x121 + 0x7f,
+        0x143 => 0xad,
+        _ => return None,
+    };
+
+    Some(byte as u8)
+}
+
+/// Spell the UTF-8 bytes of `text` with one character per byte
+pub(crate) fn encode(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.bytes().map(to_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_gpt2_mapping() {
+        assert_eq!(to_char(b' '), 'Ġ');
+        assert_eq!(to_char(b'\n'), 'Ċ');
+        assert_eq!(to_char(b'a'), 'a');
+        assert_eq!(to_char(0x7f), '\u{121}');
+        assert_eq!(to_char(0xad), '\u{143}');
+        assert_eq!(encode("é!").collect::<String>(), "Ã©!");
+    }
+
+    #[test]
+    fn every_byte_round_trips() {
+        let chars: Vec<char> = (0..=255).map(to_char).collect();
+        for (byte, &c) in chars.iter().enumerate() {
+            assert_eq!(to_byte(c), Some(byte as u8));
+        }
+        let mut unique = chars.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), 256);
+        assert_eq!(to_byte('\u{144}'), None);
+        assert_eq!(to_byte('€'), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! Character n-grams
+//!
+//! Terms are wrapped in `<` and `>` and split into every run of `n`
+//! characters, as fastText does to build vectors for words it has
+//! never seen from the vectors of their n-grams.
+
+use serde_json::{json, Value};
+
+use crate::error::Result;
+use crate::model::config;
+
+/// Marks the start of a term
+const BOW: char = '<';
+/// Marks the end of a term
+const EOW: char = '>';
+
+/// Splitting of terms into their character n-grams
+///
+/// A term gives the whole term wrapped in boundary markers, then every
+/// n-gram of the wrapped term for `n` in the range, shortest first. A
+/// boundary marker is never an n-gram on its own.
+///
+/// ```
+/// use tok::CharNgrams;
+///
+/// let ngrams = CharNgrams::new(3, 4);
+/// assert_eq!(ngrams.ngrams("where"), vec!["<where>", "<wh", "whe", "her", "ere", "re>",
+///                                         "<whe", "wher", "here", "ere>"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharNgrams {
+    min_n: usize,
+    max_n: usize,
+}
+
+impl Default for CharNgrams {
+    fn default() -> Self {
+        CharNgrams::new(3, 6)
+    }
+}
+
+impl CharNgrams {
+    /// Split terms into n-grams of `min_n` to `max_n` characters
+    ///
+    /// The lengths count the boundary markers. fastText uses 3 to 6,
+    /// which is the [`Default`].
+    pub fn new(min_n: usize, max_n: usize) -> Self {
+        let min_n = min_n.max(1);
+        CharNgrams { min_n, max_n: max_n.max(min_n) }
+    }
+
+    /// Get the shortest n-gram length
+    pub fn min_n(&self) -> usize {
+        self.min_n
+    }
+
+    /// Get the longest n-gram length
+    pub fn max_n(&self) -> usize {
+        self.max_n
+    }
+
+    /// Get the wrapped term followed by its n-grams
+    pub fn ngrams(&self, term: &str) -> Vec<String> {
+        self.ngram_spans(term).into_iter().map(|(gram, _)| gram).collect()
+    }
+
+    /// Get the n-grams of a term with the byte span of the term each covers
+    pub(crate) fn ngram_spans(&self, term: &str) -> Vec<(String, (usize, usize))> {
+        let wrapped: Vec<char> = Some(BOW).into_iter()
+                                          .chain(term.chars())
+                                          .chain(Some(EOW))
+                                          .collect();
+        let bounds: Vec<usize> = term.char_indices()
+                                     .map(|(at, _)| at)
+                                     .chain(Some(term.len()))
+                                     .collect();
+        let chars = bounds.len() - 1;
+
+        let mut ngrams = vec![(wrapped.iter().collect(), (0, term.len()))];
+        for n in self.min_n..=self.max_n.min(wrapped.len()) {
+            for (start, gram) in wrapped.windows(n).enumerate() {
+                let marker = n == 1 && (start == 0 || start == wrapped.len() - 1);
+                if !marker && n < wrapped.len() {
+                    let span = (bounds[start.saturating_sub(1)], bounds[(start + n - 1).min(chars)]);
+                    ngrams.push((gram.iter().collect(), span));
+                }
+            }
+        }
+
+        ngrams
+    }
+
+    pub(crate) fn to_json(self) -> Value {
+        json!({ "type": "char_ngrams", "min_n": self.min_n, "max_n": self.max_n })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<CharNgrams> {
+        let length = |key| {
+            value.get(key)
+                 .and_then(Value::as_u64)
+                 .ok_or_else(|| config(format!("character n-gram {} is not a number", key)))
+        };
+
+        Ok(CharNgrams::new(length("min_n")? as usize, length("max_n")? as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_terms_in_boundary_markers() {
+        let ngrams = CharNgrams::new(1, 3);
+        assert_eq!(ngrams.ngrams("ab"), vec!["<ab>", "a", "b", "<a", "ab", "b>", "<ab", "ab>"]);
+        assert_eq!(CharNgrams::new(3, 6).ngrams("a"), vec!["<a>"]);
+        assert_eq!(CharNgrams::default().ngrams("né"), vec!["<né>", "<né", "né>"]);
+        assert_eq!(CharNgrams::new(5, 2), CharNgrams::new(5, 5));
+    }
+
+    #[test]
+    fn spans_leave_out_boundary_markers() {
+        let spans: Vec<(usize, usize)> = CharNgrams::new(2, 3).ngram_spans("né")
+                                                              .into_iter()
+                                                              .map(|(_, span)| span)
+                                                              .collect();
+        // `<né>`, then `<n`, `né`, `é>`, then `<né`, `né>`
+        assert_eq!(spans, vec![(0, 3), (0, 1), (0, 3), (1, 3), (0, 3), (0, 3)]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Subword models
+//!
+//! A [`Model`] decides how each term produced by the tokenizer maps to
+//! vocabulary entries. Word-level vocabularies look terms up whole;
+//! subword models split them into pieces first, so terms never seen
+//! while building can still be encoded.
+
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+use crate::vocab::Vocab;
+
+pub(crate) mod bpe;
+pub(crate) mod byte_level;
+pub(crate) mod chars;
+pub(crate) mod unigram;
+pub(crate) mod wordpiece;
+
+pub use self::bpe::{Bpe, BpeTrainer};
+pub use self::chars::CharNgrams;
+pub use self::unigram::{Unigram, UnigramTrainer};
+pub use self::wordpiece::{WordPiece, WordPieceTrainer};
+
+/// How terms are split into vocabulary entries
+///
+/// The model is saved with the vocabulary, so a loaded vocabulary
+/// segments text the same way as the one that was written.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Model {
+    /// Every term is a single entry
+    #[default]
+    Word,
+    /// Every character is an entry
+    Chars,
+    /// Terms are split into their character n-grams, see [`CharNgrams`]
+    CharNgrams(CharNgrams),
+    /// Terms are split by byte-pair encoding merges
+    Bpe(Bpe),
+    /// Terms are split greedily into the longest known pieces
+    WordPiece(WordPiece),
+    /// Terms are split into their most probable pieces
+    Unigram(Unigram),
+}
+
+impl Model {
+    /// Split a term into entries without looking them up
+    ///
+    /// This is what a vocabulary built with the model counts. WordPiece
+    /// needs its vocabulary to split, so gives `None`.
+    pub(crate) fn split(&self, term: &str) -> Option<Vec<String>> {
+        let pieces = match self {
+            Model::Word => vec![term.to_owned()],
+            Model::Chars => term.chars().map(String::from).collect(),
+            Model::CharNgrams(ngrams) => ngrams.ngrams(term),
+            Model::Bpe(bpe) => bpe.pieces(term),
+            Model::Unigram(unigram) => unigram.pieces(term),
+            Model::WordPiece(_) => return None,
+        };
+
+        Some(pieces)
+    }
+
+    /// Get the ids of every piece of `terms`
+    ///
+    /// See [`Model::encode_term`].
+    pub(crate) fn encode_terms<L>(&self, terms: &[String], lookup: L, unk: Option<i32>) -> Vec<i32>
+    where
+        L: Fn(&str) -> Option<i32>,
+    {
+        let mut ids = Vec::with_capacity(terms.len());
+        for term in terms {
+            self.encode_term(term, &lookup, unk, &mut ids);
+        }
+
+        ids
+    }
+
+    /// Get the ids of the `n` most probable ways to split `terms`, best first
+    ///
+    /// Only Unigram models have more than one way; the others give
+    /// their single encoding.
+    pub(crate) fn encode_nbest<L>(&self, terms: &[String], n: usize, lookup: L, unk: Option<i32>) -> Vec<Vec<i32>>
+    where
+        L: Fn(&str) -> Option<i32>,
+    {
+        let unigram = match self {
+            _ if n == 0 => return Vec::new(),
+            Model::Unigram(unigram) => unigram,
+            _ => return vec![self.encode_terms(terms, lookup, unk)],
+        };
+
+        let mut best: Vec<(Vec<i32>, f64)> = vec![(Vec::new(), 0.0)];
+        for term in terms {
+            let splits = unigram.nbest(term, n);
+            let mut joined = Vec::with_capacity(best.len() * splits.len());
+            for (ids, score) in &best {
+                for (pieces, split_score) in &splits {
+                    let mut ids = ids.clone();
+                    ids.extend(pieces.iter().filter_map(|piece| lookup(piece).or(unk)));
+                    joined.push((ids, score + split_score));
+                }
+            }
+            joined.sort_by(|a, b| b.1.total_cmp(&a.1));
+            joined.truncate(n);
+            best = joined;
+        }
+
+        best.into_iter().map(|(ids, _)| ids).collect()
+    }
+
+    /// Get the ids of a random split of `terms`
+    ///
+    /// Only Unigram models draw a split, see [`Unigram::sample`]; the
+    /// others give their single encoding.
+    pub(crate) fn encode_sample<L>(&self, terms: &[String], alpha: f64, seed: u64, lookup: L, unk: Option<i32>) -> Vec<i32>
+    where
+        L: Fn(&str) -> Option<i32>,
+    {
+        let unigram = match self {
+            Model::Unigram(unigram) => unigram,
+            _ => return self.encode_terms(terms, lookup, unk),
+        };
+
+        let mut rng = fastrand::Rng::with_seed(seed);
+        let mut ids = Vec::with_capacity(terms.len());
+        for term in terms {
+            let pieces = unigram.sample_with(term, alpha, &mut rng);
+            ids.extend(pieces.iter().filter_map(|piece| lookup(piece).or(unk)));
+        }
+
+        ids
+    }
+
+    /// Append the ids of every piece of `term` to `ids`
+    ///
+    /// `lookup` finds the id of a piece. Pieces without one take `unk`,
+    /// or are skipped when it is `None`.
+    pub(crate) fn encode_term<L>(&self, term: &str, lookup: L, unk: Option<i32>, ids: &mut Vec<i32>)
+    where
+        L: Fn(&str) -> Option<i32>,
+    {
+        self.encode_spans(term, lookup, unk, |id, _| ids.push(id));
+    }
+
+    /// Pass the id of every piece of `term` to `found`, with the byte span of the term it covers
+    ///
+    /// Ids are looked up as in [`Model::encode_term`]. An unknown term
+    /// that cannot be split covers the whole term.
+    pub(crate) fn encode_spans<L, F>(&self, term: &str, lookup: L, unk: Option<i32>, mut found: F)
+    where
+        L: Fn(&str) -> Option<i32>,
+        F: FnMut(i32, (usize, usize)),
+    {
+        let mut pieces = |pieces: Vec<(String, (usize, usize))>| {
+            for (piece, span) in pieces {
+                if let Some(id) = lookup(&piece).or(unk) {
+                    found(id, span);
+                }
+            }
+        };
+        match self {
+            Model::Word => {
+                if let Some(id) = lookup(term).or(unk) {
+                    found(id, (0, term.len()));
+                }
+            }
+            Model::Chars => {
+                for (at, c) in term.char_indices() {
+                    if let Some(id) = lookup(c.encode_utf8(&mut [0; 4])).or(unk) {
+                        found(id, (at, at + c.len_utf8()));
+                    }
+                }
+            }
+            Model::CharNgrams(ngrams) => pieces(ngrams.ngram_spans(term)),
+            Model::Bpe(bpe) => pieces(bpe.piece_spans(term)),
+            Model::WordPiece(wordpiece) => match wordpiece.split(term, &lookup) {
+                Some(split) => split.into_iter().for_each(|(id, span)| found(id, span)),
+                None => unk.into_iter().for_each(|id| found(id, (0, term.len()))),
+            },
+            Model::Unigram(unigram) => {
+                let mut start = 0;
+                pieces(unigram.pieces(term)
+                              .into_iter()
+                              .map(|piece| {
+                                  start += piece.len();
+                                  let span = (start - piece.len(), start);
+                                  (piece, span)
+                              })
+                              .collect())
+            }
+        }
+    }
+
+    /// Append the bytes a vocabulary piece stands for to `bytes`
+    ///
+    /// Byte-level models map each character back to its byte, and
+    /// WordPiece joins continuing pieces and spaces out the rest; other
+    /// models keep the piece's own UTF-8.
+    pub(crate) fn piece_bytes(&self, piece: &str, bytes: &mut Vec<u8>) {
+        match self {
+            Model::Bpe(bpe) => bpe.piece_bytes(piece, bytes),
+            Model::WordPiece(wordpiece) => wordpiece.piece_bytes(piece, bytes),
+            Model::Word | Model::Chars | Model::CharNgrams(_) | Model::Unigram(_) => {
+                bytes.extend_from_slice(piece.as_bytes())
+            }
+        }
+    }
+
+    /// Describe the model as a JSON value for saving
+    pub(crate) fn to_json(&self) -> Value {
+        match self {
+            Model::Word => json!({ "type": "word" }),
+            Model::Chars => json!({ "type": "chars" }),
+            Model::CharNgrams(ngrams) => ngrams.to_json(),
+            Model::Bpe(bpe) => bpe.to_json(),
+            Model::WordPiece(wordpiece) => wordpiece.to_json(),
+            Model::Unigram(unigram) => unigram.to_json(),
+        }
+    }
+
+    /// Read a model saved by [`Model::to_json`]
+    pub(crate) fn from_json(value: &Value) -> Result<Model> {
+        match value.get("type").and_then(Value::as_str) {
+            Some("word") => Ok(Model::Word),
+            Some("chars") => Ok(Model::Chars),
+            Some("char_ngrams") => Ok(Model::CharNgrams(CharNgrams::from_json(value)?)),
+            Some("bpe") => Ok(Model::Bpe(Bpe::from_json(value)?)),
+            Some("wordpiece") => Ok(Model::WordPiece(WordPiece::from_json(value)?)),
+            Some("unigram") => Ok(Model::Unigram(Unigram::from_json(value)?)),
+            _ => Err(config("unknown model type")),
+        }
+    }
+}
+
+/// Get the non-special terms of a word-level vocabulary with their counts
+///
+/// Terms are sorted, so training does not depend on hash order.
+pub(crate) fn training_words(vocab: &Vocab) -> Result<Vec<(&String, u64)>> {
+    if *vocab.model() != Model::Word {
+        return Err(config("subword models are trained from a word-level vocabulary"));
+    }
+    if !vocab.has_frequencies() {
+        return Err(config("the vocabulary has no term frequencies to train on"));
+    }
+
+    let mut words: Vec<(&String, u64)> = vocab.counts()
+                                              .iter()
+                                              .filter(|(term, _)| !vocab.is_special(term))
+                                              .map(|(term, &count)| (term, count))
+                                              .collect();
+    words.sort_unstable();
+
+    Ok(words)
+}
+
+/// Build a [`Error::Config`] from a reason
+pub(crate) fn config<R: Into<String>>(reason: R) -> Error {
+    Error::Config { reason: reason.into() }
+}
@@ -0,0 +1,591 @@
+//! Unigram language model
+//!
+//! Every piece has a log probability, and a term is split into the
+//! pieces whose probabilities have the highest product. Training starts
+//! from frequent substrings of the terms and alternates
+//! expectation-maximization with pruning the pieces whose loss would
+//! cost the least, as SentencePiece does.
+
+use std::collections::HashMap;
+use std::iter;
+
+use serde_json::{json, Value};
+
+use crate::error::Result;
+use crate::model::{config, training_words, Model};
+use crate::vocab::Vocab;
+
+/// Score of characters that are not pieces, below the lowest piece score
+const UNK_PENALTY: f64 = 10.0;
+
+/// Pieces with their log probabilities
+///
+/// Built by [`UnigramTrainer::train`], or from scores learned elsewhere.
+///
+/// ```
+/// use tok::Unigram;
+///
+/// let unigram = Unigram::new(vec![("h".into(), -3.0), ("u".into(), -3.0), ("g".into(), -3.0),
+///                                 ("hu".into(), -4.0), ("ug".into(), -2.0)]);
+/// assert_eq!(unigram.pieces("hug"), vec!["h", "ug"]);
+/// assert_eq!(unigram.nbest("hug", 2)[1].0, vec!["hu", "g"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Unigram {
+    scores: Vec<(String, f64)>,
+    /// Position of each piece in `scores`
+    ids: HashMap<String, usize>,
+    /// Length of the longest piece, in characters
+    max_chars: usize,
+    /// Score of a character that is not a piece
+    unk_score: f64,
+}
+
+impl PartialEq for Unigram {
+    fn eq(&self, other: &Unigram) -> bool {
+        self.scores == other.scores
+    }
+}
+
+/// A piece of a term in the segmentation lattice
+#[derive(Debug, Clone)]
+struct Edge {
+    /// Character position the piece starts at
+    start: usize,
+    /// Position of the piece in the scores, or `None` for an unknown character
+    piece: Option<usize>,
+    score: f64,
+}
+
+/// Every piece of a term, by the character position it ends at
+struct Lattice {
+    /// Byte offset of every character boundary
+    bounds: Vec<usize>,
+    ends: Vec<Vec<Edge>>,
+}
+
+impl Lattice {
+    fn len(&self) -> usize {
+        self.bounds.len() - 1
+    }
+
+    fn text<'a>(&self, term: &'a str, edge: &Edge, end: usize) -> &'a str {
+        &term[self.bounds[edge.start]..self.bounds[end]]
+    }
+
+    /// Log of the summed probabilities of every path to each position
+    ///
+    /// Piece scores are multiplied by `alpha` first.
+    fn forward(&self, alpha: f64) -> Vec<f64> {
+        let mut fwd = vec![0.0; self.len() + 1];
+        for end in 1..=self.len() {
+            fwd[end] = log_sum_exp(self.ends[end].iter().map(|edge| fwd[edge.start] + alpha * edge.score));
+        }
+
+        fwd
+    }
+
+    /// Log of the summed probabilities of every path from each position
+    fn backward(&self) -> Vec<f64> {
+        let mut bwd = vec![f64::NEG_INFINITY; self.len() + 1];
+        bwd[self.len()] = 0.0;
+        for end in (1..=self.len()).rev() {
+            for edge in &self.ends[end] {
+                bwd[edge.start] = log_sum_exp([bwd[edge.start], bwd[end] + edge.score].iter().copied());
+            }
+        }
+
+        bwd
+    }
+}
+
+fn log_sum_exp<I: Iterator<Item = f64> + Clone>(values: I) -> f64 {
+    let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+
+    max + values.map(|value| (value - max).exp()).sum::<f64>().ln()
+}
+
+impl Unigram {
+    /// Create a model from pieces and their log probabilities
+    ///
+    /// # Arguments
+    ///
+    /// * `scores` - each piece with its log probability
+    pub fn new(scores: Vec<(String, f64)>) -> Self {
+        let ids = scores.iter()
+                        .enumerate()
+                        .map(|(idx, (piece, _))| (piece.to_owned(), idx))
+                        .collect();
+        let max_chars = scores.iter().map(|(piece, _)| piece.chars().count()).max().unwrap_or(1);
+        let lowest = scores.iter().map(|&(_, score)| score).fold(0.0, f64::min);
+
+        Unigram { scores, ids, max_chars, unk_score: lowest - UNK_PENALTY }
+    }
+
+    /// Get every piece with its log probability
+    pub fn scores(&self) -> &[(String, f64)] {
+        &self.scores
+    }
+
+    /// Split a term into its most probable pieces
+    ///
+    /// Characters that are not pieces stand alone.
+    pub fn pieces(&self, term: &str) -> Vec<String> {
+        self.nbest(term, 1).pop().map(|(pieces, _)| pieces).unwrap_or_default()
+    }
+
+    /// Get the `n` most probable ways to split a term, best first
+    ///
+    /// Each split comes with its log probability.
+    pub fn nbest(&self, term: &str, n: usize) -> Vec<(Vec<String>, f64)> {
+        let lattice = self.lattice(term);
+        let len = lattice.len();
+        // The best paths to each position, as their score, the edge they
+        // end with and which of the paths to its start they extend.
+        let mut paths: Vec<Vec<(f64, usize, usize)>> = vec![Vec::new(); len + 1];
+        paths[0].push((0.0, 0, 0));
+        for end in 1..=len {
+            let mut found = Vec::new();
+            for (idx, edge) in lattice.ends[end].iter().enumerate() {
+                for (rank, &(score, _, _)) in paths[edge.start].iter().enumerate() {
+                    found.push((score + edge.score, idx, rank));
+                }
+            }
+            found.sort_by(|a, b| b.0.total_cmp(&a.0));
+            found.truncate(n);
+            paths[end] = found;
+        }
+
+        paths[len].iter()
+                  .map(|&(score, mut idx, mut rank)| {
+                      let mut pieces = Vec::new();
+                      let mut end = len;
+                      while end > 0 {
+                          let edge = &lattice.ends[end][idx];
+                          pieces.push(lattice.text(term, edge, end).to_owned());
+                          let (_, prev_idx, prev_rank) = paths[edge.start][rank];
+                          end = edge.start;
+                          idx = prev_idx;
+                          rank = prev_rank;
+                      }
+                      pieces.reverse();
+                      (pieces, score)
+                  })
+                  .collect()
+    }
+
+    /// Draw a split of a term at random
+    ///
+    /// Splits are drawn with probability proportional to their
+    /// probability raised to `alpha`: small values flatten the
+    /// distribution, and large ones approach [`Unigram::pieces`].
+    /// Draws are repeatable for a given `seed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - the term to split
+    /// * `alpha` - smoothing of the split probabilities
+    /// * `seed` - seeds the random draw
+    pub fn sample(&self, term: &str, alpha: f64, seed: u64) -> Vec<String> {
+        self.sample_with(term, alpha, &mut fastrand::Rng::with_seed(seed))
+    }
+
+    pub(crate) fn sample_with(&self, term: &str, alpha: f64, rng: &mut fastrand::Rng) -> Vec<String> {
+        let lattice = self.lattice(term);
+        let fwd = lattice.forward(alpha);
+
+        let mut pieces = Vec::new();
+        let mut end = lattice.len();
+        while end > 0 {
+            let edges = &lattice.ends[end];
+            let mut draw = rng.f64();
+            let mut chosen = &edges[edges.len() - 1];
+            for edge in edges {
+                let probability = (fwd[edge.start] + alpha * edge.score - fwd[end]).exp();
+                if draw < probability {
+                    chosen = edge;
+                    break;
+                }
+                draw -= probability;
+            }
+            pieces.push(lattice.text(term, chosen, end).to_owned());
+            end = chosen.start;
+        }
+        pieces.reverse();
+
+        pieces
+    }
+
+    /// Find every piece of `term`, with a lone unknown character
+    /// wherever no single-character piece exists
+    fn lattice(&self, term: &str) -> Lattice {
+        let bounds: Vec<usize> = term.char_indices()
+                                     .map(|(at, _)| at)
+                                     .chain(iter::once(term.len()))
+                                     .collect();
+        let len = bounds.len() - 1;
+        let mut ends = vec![Vec::new(); len + 1];
+        for start in 0..len {
+            let mut single = false;
+            for end in start + 1..=len.min(start + self.max_chars) {
+                if let Some(&idx) = self.ids.get(&term[bounds[start]..bounds[end]]) {
+                    single |= end == start + 1;
+                    ends[end].push(Edge { start, piece: Some(idx), score: self.scores[idx].1 });
+                }
+            }
+            if !single {
+                ends[start + 1].push(Edge { start, piece: None, score: self.unk_score });
+            }
+        }
+
+        Lattice { bounds, ends }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({ "type": "unigram", "scores": self.scores })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Unigram> {
+        let scores = value.get("scores")
+                          .and_then(|scores| serde_json::from_value(scores.clone()).ok())
+                          .ok_or_else(|| config("Unigram scores are not a list of pieces and scores"))?;
+
+        Ok(Unigram::new(scores))
+    }
+}
+
+/// Options for learning a Unigram language model
+///
+/// Pieces are learned from the term frequencies a word-level
+/// vocabulary gathered while it was built, so training never reads
+/// the corpus again.
+///
+/// ```no_run
+/// use tok::{UnigramTrainer, Vocab};
+///
+/// let words = Vocab::new("corpus.txt")?;
+/// let pieces = UnigramTrainer::new(8_000).train(&words)?;
+/// let ids = pieces.encode_sample("unseen words still encode", 0.1, 42);
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnigramTrainer {
+    /// Target number of entries, special tokens included
+    vocab_size: usize,
+    /// Number of substrings training starts from
+    seed_size: usize,
+    /// Longest piece, in characters
+    max_piece_chars: usize,
+    /// Share of pieces kept by each pruning round
+    shrinking_factor: f64,
+    /// Expectation-maximization rounds between prunings
+    em_iterations: usize,
+}
+
+impl UnigramTrainer {
+    /// Learn pieces until the vocabulary holds `vocab_size` entries
+    ///
+    /// The vocabulary never has fewer entries than the special tokens
+    /// and every character of the training terms, and has fewer than
+    /// `vocab_size` when the terms are split with fewer pieces.
+    pub fn new(vocab_size: usize) -> Self {
+        UnigramTrainer {
+            vocab_size,
+            seed_size: 1_000_000,
+            max_piece_chars: 16,
+            shrinking_factor: 0.75,
+            em_iterations: 2,
+        }
+    }
+
+    /// Start from at most `seed_size` of the most frequent substrings
+    ///
+    /// Defaults to 1,000,000.
+    pub fn seed_size(mut self, seed_size: usize) -> Self {
+        self.seed_size = seed_size;
+        self
+    }
+
+    /// Learn no piece longer than `max_piece_chars` characters
+    ///
+    /// Defaults to 16.
+    pub fn max_piece_chars(mut self, max_piece_chars: usize) -> Self {
+        self.max_piece_chars = max_piece_chars.max(1);
+        self
+    }
+
+    /// Keep this share of the pieces each pruning round, between 0 and 1
+    ///
+    /// Defaults to 0.75.
+    pub fn shrinking_factor(mut self, shrinking_factor: f64) -> Self {
+        self.shrinking_factor = shrinking_factor;
+        self
+    }
+
+    /// Run `em_iterations` rounds of expectation-maximization between prunings
+    ///
+    /// Defaults to 2.
+    pub fn em_iterations(mut self, em_iterations: usize) -> Self {
+        self.em_iterations = em_iterations.max(1);
+        self
+    }
+
+    /// Learn pieces from the term frequencies of a word-level vocabulary
+    ///
+    /// The result keeps the special tokens, normalizer and tokenizer of
+    /// `vocab`. Entries follow the special tokens from most to least
+    /// probable, and frequencies count each piece in the most probable
+    /// splits of the training terms.
+    ///
+    /// # Arguments
+    ///
+    /// * `vocab` - vocabulary built from a corpus, with frequencies
+    pub fn train(&self, vocab: &Vocab) -> Result<Vocab> {
+        if !(self.shrinking_factor > 0.0 && self.shrinking_factor < 1.0) {
+            return Err(config("the shrinking factor must be between 0 and 1"));
+        }
+        let words = training_words(vocab)?;
+        let target = self.vocab_size.saturating_sub(vocab.special_tokens().len());
+
+        let mut model = self.seed(&words);
+        loop {
+            for _ in 0..self.em_iterations {
+                let expected = expectations(&model, &words);
+                model = maximize(&model, &expected);
+            }
+            if model.scores.len() <= target {
+                break;
+            }
+            let expected = expectations(&model, &words);
+            let size = target.max((model.scores.len() as f64 * self.shrinking_factor) as usize);
+            let pruned = prune(&model, &expected, size);
+            if pruned.scores.len() == model.scores.len() {
+                break;
+            }
+            model = pruned;
+        }
+
+        let mut scores = model.scores.clone();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let model = Unigram::new(scores);
+
+        let mut counts = HashMap::new();
+        for &(term, count) in &words {
+            for piece in model.pieces(term) {
+                *counts.entry(piece).or_insert(0) += count;
+            }
+        }
+        let terms = model.scores.iter().map(|(piece, _)| piece.to_owned()).collect();
+
+        Ok(vocab.derive(terms, counts, Model::Unigram(model)))
+    }
+
+    /// Score every character and the most frequent longer substrings
+    /// by how often they occur
+    fn seed(&self, words: &[(&String, u64)]) -> Unigram {
+        let mut chars: HashMap<String, u64> = HashMap::new();
+        let mut substrings: HashMap<&str, u64> = HashMap::new();
+        for &(term, count) in words {
+            let bounds: Vec<usize> = term.char_indices()
+                                         .map(|(at, _)| at)
+                                         .chain(iter::once(term.len()))
+                                         .collect();
+            for start in 0..bounds.len() - 1 {
+                *chars.entry(term[bounds[start]..bounds[start + 1]].to_owned()).or_default() += count;
+                for end in start + 2..bounds.len().min(start + self.max_piece_chars + 1) {
+                    *substrings.entry(&term[bounds[start]..bounds[end]]).or_default() += count;
+                }
+            }
+        }
+
+        // Longer substrings save more pieces per occurrence
+        let mut seeds: Vec<(&str, u64)> = substrings.into_iter().collect();
+        seeds.sort_by(|a, b| {
+            let weight = |(piece, count): &(&str, u64)| count * piece.chars().count() as u64;
+            weight(b).cmp(&weight(a)).then_with(|| a.0.cmp(b.0))
+        });
+        seeds.truncate(self.seed_size);
+
+        let mut scores: Vec<(String, u64)> = chars.into_iter().collect();
+        scores.extend(seeds.into_iter().map(|(piece, count)| (piece.to_owned(), count)));
+        scores.sort_unstable();
+        let total = scores.iter().map(|&(_, count)| count as f64).sum::<f64>().ln();
+
+        Unigram::new(scores.into_iter()
+                           .map(|(piece, count)| (piece, (count as f64).ln() - total))
+                           .collect())
+    }
+}
+
+/// Expected number of times each piece is used, over every split of
+/// every term weighted by its probability
+fn expectations(model: &Unigram, words: &[(&String, u64)]) -> Vec<f64> {
+    let mut expected = vec![0.0; model.scores.len()];
+    for &(term, count) in words {
+        let lattice = model.lattice(term);
+        let fwd = lattice.forward(1.0);
+        let bwd = lattice.backward();
+        let total = fwd[lattice.len()];
+        for (end, edges) in lattice.ends.iter().enumerate() {
+            for edge in edges {
+                if let Some(idx) = edge.piece {
+                    let posterior = (fwd[edge.start] + edge.score + bwd[end] - total).exp();
+                    expected[idx] += count as f64 * posterior;
+                }
+            }
+        }
+    }
+
+    expected
+}
+
+/// Re-score pieces from their expected counts, dropping the rarely used
+///
+/// Single characters are always kept, so every term can still be split.
+fn maximize(model: &Unigram, expected: &[f64]) -> Unigram {
+    let kept: Vec<(&str, f64)> = model.scores
+                                      .iter()
+                                      .zip(expected)
+                                      .filter(|((piece, _), &count)| count >= 0.5 || is_char(piece))
+                                      .map(|((piece, _), &count)| (piece.as_str(), count.max(0.5)))
+                                      .collect();
+    let total = kept.iter().map(|&(_, count)| count).sum::<f64>().ln();
+
+    Unigram::new(kept.into_iter()
+                     .map(|(piece, count)| (piece.to_owned(), count.ln() - total))
+                     .collect())
+}
+
+/// Keep the `size` pieces whose removal would cost the most likelihood
+///
+/// Removing a piece moves its uses to the next best split of its text.
+/// Single characters are always kept.
+fn prune(model: &Unigram, expected: &[f64], size: usize) -> Unigram {
+    let mut chars = Vec::new();
+    let mut losses = Vec::new();
+    for (idx, (piece, score)) in model.scores.iter().enumerate() {
+        if is_char(piece) {
+            chars.push(idx);
+            continue;
+        }
+        let alternative = model.nbest(piece, 2)
+                               .into_iter()
+                               .find(|(pieces, _)| pieces.len() > 1)
+                               .map_or(f64::NEG_INFINITY, |(_, score)| score);
+        losses.push((expected[idx] * (score - alternative), idx));
+    }
+    losses.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    losses.truncate(size.saturating_sub(chars.len()));
+
+    let mut kept: Vec<usize> = chars.into_iter().chain(losses.into_iter().map(|(_, idx)| idx)).collect();
+    kept.sort_unstable();
+
+    Unigram::new(kept.into_iter().map(|idx| model.scores[idx].clone()).collect())
+}
+
+fn is_char(piece: &str) -> bool {
+    piece.chars().nth(1).is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vocab::UNK;
+
+    fn hug() -> Unigram {
+        Unigram::new(vec![("h".into(), -3.0), ("u".into(), -3.0), ("g".into(), -3.0),
+                          ("hu".into(), -4.0), ("ug".into(), -2.0)])
+    }
+
+    #[test]
+    fn nbest_lists_splits_by_probability() {
+        let nbest = hug().nbest("hug", 5);
+        let splits: Vec<Vec<String>> = nbest.iter().map(|(pieces, _)| pieces.clone()).collect();
+        assert_eq!(splits, vec![vec!["h", "ug"], vec!["hu", "g"], vec!["h", "u", "g"]]);
+        assert_eq!(nbest.iter().map(|&(_, score)| score).collect::<Vec<_>>(), vec![-5.0, -7.0, -9.0]);
+        assert_eq!(hug().pieces("thug"), vec!["t", "h", "ug"]);
+        assert_eq!(hug().nbest("", 3), vec![(Vec::new(), 0.0)]);
+    }
+
+    #[test]
+    fn samples_follow_split_probabilities() {
+        let unigram = hug();
+        assert_eq!(unigram.sample("hug", 1.0, 7), unigram.sample("hug", 1.0, 7));
+
+        let best = (0..1000).filter(|&seed| unigram.sample("hug", 1.0, seed) == ["h", "ug"]).count();
+        // e^-5 / (e^-5 + e^-7 + e^-9) is about 0.87
+        assert!((820..920).contains(&best), "{}", best);
+        let flat = (0..1000).filter(|&seed| unigram.sample("hug", 0.0, seed) == ["h", "ug"]).count();
+        assert!((280..390).contains(&flat), "{}", flat);
+    }
+
+    fn trained(size: usize) -> Vocab {
+        let corpus = "the cat sat on the mat with the hat and the cats sat on the mats ".repeat(5);
+        let words = Vocab::builder().unk_token(UNK).build_reader(corpus.as_bytes()).unwrap();
+        UnigramTrainer::new(size).train(&words).unwrap()
+    }
+
+    #[test]
+    fn trains_to_the_target_size() {
+        let vocab = trained(16);
+        assert_eq!(vocab.size(), 16);
+        assert_eq!(trained(100).size(), 1 + 12 + 10);
+        for c in "thecasomwind".chars() {
+            assert!(vocab.token_to_id(&c.to_string()).is_some(), "{}", c);
+        }
+        assert_eq!(vocab.token_to_id(UNK), Some(0));
+        assert_eq!(vocab.decode(&vocab.encode("the")), vec!["the"]);
+        assert!(vocab.frequency("the") >= 20);
+
+        let unseen = vocab.encode("thematic");
+        assert!(!unseen.contains(&0));
+        assert_eq!(vocab.decode(&unseen).concat(), "thematic");
+        assert_eq!(vocab.encode("zebra")[0], 0);
+    }
+
+    #[test]
+    fn nbest_and_sampled_encodings() {
+        let vocab = trained(20);
+        let nbest = vocab.encode_nbest("cats mat", 4);
+        assert_eq!(nbest.len(), 4);
+        assert_eq!(nbest[0], vocab.encode("cats mat"));
+        for ids in &nbest {
+            assert_eq!(vocab.decode(ids).concat(), "catsmat");
+        }
+        assert!(vocab.encode_nbest("cats", 0).is_empty());
+
+        let sampled: Vec<Vec<i32>> = (0..50).map(|seed| vocab.encode_sample("cats mat", 0.1, seed)).collect();
+        assert!(sampled.iter().any(|ids| *ids != nbest[0]));
+        assert!(sampled.iter().all(|ids| vocab.decode(ids).concat() == "catsmat"));
+
+        let words = Vocab::from_json(r#"{"cats": 0}"#).unwrap();
+        assert_eq!(words.encode_nbest("cats", 3), vec![vec![0]]);
+        assert_eq!(words.encode_sample("cats", 0.1, 1), vec![0]);
+    }
+
+    #[test]
+    fn model_is_saved_with_the_vocabulary() {
+        let vocab = trained(30);
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        vocab.write(path).unwrap();
+        let loaded = Vocab::load(path).unwrap();
+        assert_eq!(loaded, vocab);
+        assert_eq!(loaded.encode_nbest("thematic", 3), vocab.encode_nbest("thematic", 3));
+
+        vocab.write_binary(path).unwrap();
+        let mapped = crate::MappedVocab::open(path).unwrap();
+        assert_eq!(mapped.encode_sample("thematic", 0.5, 3), vocab.encode_sample("thematic", 0.5, 3));
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let words = Vocab::builder().build_reader("a b".as_bytes()).unwrap();
+        let bad = UnigramTrainer::new(10).shrinking_factor(1.0).train(&words);
+        assert!(matches!(bad, Err(crate::Error::Config { .. })));
+    }
+}
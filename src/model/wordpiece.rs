@@ -0,0 +1,260 @@
+//! WordPiece
+//!
+//! Terms are split greedily, taking the longest vocabulary entry at
+//! each position. Pieces that continue a term carry a prefix, `##` by
+//! default, as in BERT vocabularies.
+
+use serde_json::{json, Value};
+
+use crate::error::Result;
+use crate::model::bpe::BpeTrainer;
+use crate::model::{config, Model};
+use crate::vocab::Vocab;
+
+/// Greedy longest-match-first splitting into vocabulary entries
+///
+/// A term that cannot be spelled from vocabulary entries, or that is
+/// longer than the character limit, becomes a single unknown token.
+///
+/// ```
+/// use tok::{Model, Vocab, WordPiece};
+///
+/// let vocab = Vocab::from_bert("[UNK]\nun\n##aff\n##able\n")?;
+/// assert_eq!(vocab.model(), &Model::WordPiece(WordPiece::new()));
+/// assert_eq!(vocab.encode("unaffable unknowable"), vec![1, 2, 3, 0]);
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordPiece {
+    /// Prepended to pieces that continue a term
+    prefix: String,
+    /// Longer terms are unknown without being split
+    max_chars: usize,
+}
+
+impl Default for WordPiece {
+    fn default() -> Self {
+        WordPiece::new()
+    }
+}
+
+impl WordPiece {
+    /// Create a model with the `##` prefix and a 100 character limit
+    pub fn new() -> Self {
+        WordPiece { prefix: "##".to_owned(), max_chars: 100 }
+    }
+
+    /// Mark pieces that continue a term with `prefix` instead of `##`
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Treat terms longer than `max_chars` characters as unknown
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Get the prefix of pieces that continue a term
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Get the longest term, in characters, that is split
+    pub fn max_chars(&self) -> usize {
+        self.max_chars
+    }
+
+    /// Split a term with the longest entry `lookup` finds at each position
+    ///
+    /// Each entry comes with the byte span of the term it covers.
+    /// Returns `None` when some position has no entry at all.
+    pub(crate) fn split<T, L>(&self, term: &str, lookup: L) -> Option<Vec<(T, (usize, usize))>>
+    where
+        L: Fn(&str) -> Option<T>,
+    {
+        if term.chars().count() > self.max_chars {
+            return None;
+        }
+
+        let mut found = Vec::new();
+        let mut piece = String::new();
+        let mut start = 0;
+        while start < term.len() {
+            let mut ends = term[start..].char_indices().map(|(at, c)| start + at + c.len_utf8()).rev();
+            let (end, item) = ends.find_map(|end| {
+                piece.clear();
+                if start > 0 {
+                    piece.push_str(&self.prefix);
+                }
+                piece.push_str(&term[start..end]);
+                lookup(&piece).map(|item| (end, item))
+            })?;
+            found.push((item, (start, end)));
+            start = end;
+        }
+
+        Some(found)
+    }
+
+    /// Append the text a piece stands for to `bytes`
+    ///
+    /// Pieces that continue a term are joined to the previous one, and
+    /// other pieces are separated from it by a space.
+    pub(crate) fn piece_bytes(&self, piece: &str, bytes: &mut Vec<u8>) {
+        match piece.strip_prefix(self.prefix.as_str()) {
+            Some(rest) if !self.prefix.is_empty() => bytes.extend_from_slice(rest.as_bytes()),
+            _ => {
+                if !bytes.is_empty() {
+                    bytes.push(b' ');
+                }
+                bytes.extend_from_slice(piece.as_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({ "type": "wordpiece", "prefix": self.prefix, "max_chars": self.max_chars })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<WordPiece> {
+        let prefix = value.get("prefix")
+                          .and_then(Value::as_str)
+                          .ok_or_else(|| config("WordPiece prefix is not a string"))?;
+        let max_chars = value.get("max_chars")
+                             .and_then(Value::as_u64)
+                             .ok_or_else(|| config("WordPiece max_chars is not a number"))?;
+
+        Ok(WordPiece::new().with_prefix(prefix).with_max_chars(max_chars as usize))
+    }
+}
+
+/// Options for learning a WordPiece vocabulary
+///
+/// Pieces are learned the way [`BpeTrainer`] learns them, with every
+/// character that continues a term carrying the prefix. The merges are
+/// then dropped, and the vocabulary splits terms greedily instead.
+///
+/// ```no_run
+/// use tok::{Vocab, WordPieceTrainer};
+///
+/// let words = Vocab::new("corpus.txt")?;
+/// let pieces = WordPieceTrainer::new(30_000).train(&words)?;
+/// let ids = pieces.encode("unseen words still encode");
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct WordPieceTrainer {
+    /// Learns the pieces
+    bpe: BpeTrainer,
+    /// Encodes with the pieces
+    model: WordPiece,
+}
+
+impl WordPieceTrainer {
+    /// Learn pieces until the vocabulary holds `vocab_size` entries
+    ///
+    /// The vocabulary never has fewer entries than the special tokens
+    /// and every character of the training terms.
+    pub fn new(vocab_size: usize) -> Self {
+        WordPieceTrainer { bpe: BpeTrainer::new(vocab_size).prefix("##"), model: WordPiece::new() }
+    }
+
+    /// Stop once the most frequent pair is seen fewer than `min_frequency` times
+    ///
+    /// Defaults to 2, see [`BpeTrainer::min_frequency`].
+    pub fn min_frequency(mut self, min_frequency: u64) -> Self {
+        self.bpe = self.bpe.min_frequency(min_frequency);
+        self
+    }
+
+    /// Mark pieces that continue a term with `prefix` instead of `##`
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.bpe = self.bpe.prefix(prefix);
+        self.model = self.model.with_prefix(prefix);
+        self
+    }
+
+    /// Treat terms longer than `max_chars` characters as unknown when encoding
+    ///
+    /// Defaults to 100.
+    pub fn max_chars(mut self, max_chars: usize) -> Self {
+        self.model = self.model.with_max_chars(max_chars);
+        self
+    }
+
+    /// Learn pieces from the term frequencies of a word-level vocabulary
+    ///
+    /// The result keeps the special tokens, normalizer and tokenizer of
+    /// `vocab`, which should have an unknown token for terms that cannot
+    /// be spelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `vocab` - vocabulary built from a corpus, with frequencies
+    pub fn train(&self, vocab: &Vocab) -> Result<Vocab> {
+        let pieces = self.bpe.train(vocab)?;
+
+        Ok(pieces.with_model(Model::WordPiece(self.model.clone())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vocab::UNK;
+
+    fn pieces(entries: &str) -> Vocab {
+        Vocab::from_bert(entries).unwrap()
+    }
+
+    #[test]
+    fn takes_the_longest_match_first() {
+        let vocab = pieces("[UNK]\nun\nu\n##n\n##aff\n##a\n##able\n##ff\nrun\n");
+        assert_eq!(vocab.encode("unaffable"), vec![1, 4, 6]);
+        assert_eq!(vocab.encode("runaffable un"), vec![8, 4, 6, 1]);
+        assert_eq!(vocab.decode_bytes(&vocab.encode("unaffable un")), b"unaffable un");
+    }
+
+    #[test]
+    fn unspellable_and_long_terms_are_unknown() {
+        let vocab = pieces("[UNK]\nun\n##able\n");
+        assert_eq!(vocab.encode("unable unxable"), vec![1, 2, 0]);
+        let short = vocab.clone().with_model(Model::WordPiece(WordPiece::new().with_max_chars(5)));
+        assert_eq!(short.encode("unable un"), vec![0, 1]);
+
+        let no_unk = Vocab::from_json(r#"{"un": 0}"#).unwrap()
+                                                     .with_model(Model::WordPiece(WordPiece::new()));
+        assert_eq!(no_unk.encode("unx un"), vec![0]);
+    }
+
+    #[test]
+    fn trains_prefixed_pieces() {
+        let words = Vocab::builder().unk_token(UNK)
+                                    .build_reader("playing played plays player replay".as_bytes())
+                                    .unwrap();
+        let vocab = WordPieceTrainer::new(100).max_chars(12).train(&words).unwrap();
+        assert_eq!(*vocab.model(), Model::WordPiece(WordPiece::new().with_max_chars(12)));
+        assert!(vocab.token_to_id("play").is_some());
+        assert!(vocab.token_to_id("##y").is_some());
+
+        let ids = vocab.encode("replaying");
+        assert!(!ids.contains(&0), "{:?}", vocab.decode(&ids));
+        assert_eq!(vocab.decode_bytes(&ids), b"replaying");
+        assert_eq!(vocab.encode("zed"), vec![0]);
+    }
+
+    #[test]
+    fn model_is_saved_with_the_vocabulary() {
+        let vocab = pieces("[UNK]\nun\n##able\n").with_model(Model::WordPiece(WordPiece::new().with_prefix("@@")));
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        vocab.write(path).unwrap();
+        assert_eq!(Vocab::load(path).unwrap(), vocab);
+
+        vocab.write_bert(path).unwrap();
+        assert_eq!(*Vocab::load_bert(path).unwrap().model(), Model::WordPiece(WordPiece::new()));
+    }
+}
@@ -0,0 +1,413 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::iter;
+use std::str::FromStr;
+
+use unicode_normalization::char::{canonical_combining_class, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::error::{Error, Result};
+
+/// A Unicode normalization form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical composition
+    Nfc,
+    /// Compatibility composition
+    Nfkc,
+    /// Canonical decomposition
+    Nfd,
+    /// Compatibility decomposition
+    Nfkd,
+}
+
+/// One step of a [`Normalizer`]
+///
+/// Steps are saved by name: `nfc`, `nfkc`, `nfd`, `nfkd`, `lowercase`,
+/// `strip_accents`, `strip_control` and `replace_digits=<char>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizerStep {
+    /// Apply a Unicode normalization form
+    Unicode(UnicodeForm),
+    /// Lowercase every character
+    Lowercase,
+    /// Decompose characters and drop their combining marks
+    ///
+    /// The text is left decomposed; follow with [`UnicodeForm::Nfc`]
+    /// to compose what remains.
+    StripAccents,
+    /// Drop control characters other than whitespace
+    StripControl,
+    /// Replace every numeric character with the given one
+    ReplaceDigits(char),
+}
+
+const REPLACE_DIGITS: &str = "replace_digits";
+
+impl NormalizerStep {
+    fn apply(&self, text: &str) -> String {
+        match *self {
+            NormalizerStep::Unicode(UnicodeForm::Nfc) => text.nfc().collect(),
+            NormalizerStep::Unicode(UnicodeForm::Nfkc) => text.nfkc().collect(),
+            NormalizerStep::Unicode(UnicodeForm::Nfd) => text.nfd().collect(),
+            NormalizerStep::Unicode(UnicodeForm::Nfkd) => text.nfkd().collect(),
+            NormalizerStep::Lowercase => text.to_lowercase(),
+            NormalizerStep::StripAccents => text.nfd().filter(|&c| !is_combining_mark(c)).collect(),
+            NormalizerStep::StripControl => {
+                text.chars().filter(|c| !c.is_control() || c.is_whitespace()).collect()
+            }
+            NormalizerStep::ReplaceDigits(with) => {
+                text.chars().map(|c| if c.is_numeric() { with } else { c }).collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for NormalizerStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalizerStep::Unicode(UnicodeForm::Nfc) => f.write_str("nfc"),
+            NormalizerStep::Unicode(UnicodeForm::Nfkc) => f.write_str("nfkc"),
+            NormalizerStep::Unicode(UnicodeForm::Nfd) => f.write_str("nfd"),
+            NormalizerStep::Unicode(UnicodeForm::Nfkd) => f.write_str("nfkd"),
+            NormalizerStep::Lowercase => f.write_str("lowercase"),
+            NormalizerStep::StripAccents => f.write_str("strip_accents"),
+            NormalizerStep::StripControl => f.write_str("strip_control"),
+            NormalizerStep::ReplaceDigits(with) => write!(f, "{}={}", REPLACE_DIGITS, with),
+        }
+    }
+}
+
+impl FromStr for NormalizerStep {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let step = match name {
+            "nfc" => NormalizerStep::Unicode(UnicodeForm::Nfc),
+            "nfkc" => NormalizerStep::Unicode(UnicodeForm::Nfkc),
+            "nfd" => NormalizerStep::Unicode(UnicodeForm::Nfd),
+            "nfkd" => NormalizerStep::Unicode(UnicodeForm::Nfkd),
+            "lowercase" => NormalizerStep::Lowercase,
+            "strip_accents" => NormalizerStep::StripAccents,
+            "strip_control" => NormalizerStep::StripControl,
+            _ => {
+                let mut with = name.strip_prefix(REPLACE_DIGITS)
+                                   .and_then(|rest| rest.strip_prefix('='))
+                                   .unwrap_or_default()
+                                   .chars();
+                match (with.next(), with.next()) {
+                    (Some(with), None) => NormalizerStep::ReplaceDigits(with),
+                    _ => {
+                        return Err(Error::Config {
+                            reason: format!("unknown normalizer step {:?}", name),
+                        })
+                    }
+                }
+            }
+        };
+
+        Ok(step)
+    }
+}
+
+/// A chain of steps applied to text before it is tokenized
+///
+/// A vocabulary keeps the normalizer it was built with and saves it
+/// alongside its terms, so encoding applies the same rules as the
+/// build. The default chain only lowercases; start from
+/// [`Normalizer::new`] to keep case.
+///
+/// ```
+/// use tok::{Normalizer, UnicodeForm};
+///
+/// let normalizer = Normalizer::new().strip_accents()
+///                                   .unicode(UnicodeForm::Nfc)
+///                                   .replace_digits('0');
+/// assert_eq!(normalizer.normalize("Café 42"), "Cafe 00");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalizer {
+    steps: Vec<NormalizerStep>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::new().lowercase()
+    }
+}
+
+impl Normalizer {
+    /// Create a normalizer that leaves text unchanged
+    pub fn new() -> Self {
+        Normalizer { steps: Vec::new() }
+    }
+
+    /// Create a normalizer from steps applied in order
+    pub fn from_steps(steps: Vec<NormalizerStep>) -> Self {
+        Normalizer { steps }
+    }
+
+    /// Parse a normalizer from step names, such as `["nfkc", "lowercase"]`
+    ///
+    /// See [`NormalizerStep`] for the names.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        let steps = names.iter()
+                         .map(|name| name.as_ref().parse())
+                         .collect::<Result<_>>()?;
+
+        Ok(Normalizer { steps })
+    }
+
+    /// Get the names of the steps, in order
+    pub fn names(&self) -> Vec<String> {
+        self.steps.iter().map(ToString::to_string).collect()
+    }
+
+    /// Get the steps, in order
+    pub fn steps(&self) -> &[NormalizerStep] {
+        &self.steps
+    }
+
+    /// Append a step
+    pub fn step(mut self, step: NormalizerStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Apply a Unicode normalization form
+    pub fn unicode(self, form: UnicodeForm) -> Self {
+        self.step(NormalizerStep::Unicode(form))
+    }
+
+    /// Lowercase every character
+    pub fn lowercase(self) -> Self {
+        self.step(NormalizerStep::Lowercase)
+    }
+
+    /// Drop accents and other combining marks
+    pub fn strip_accents(self) -> Self {
+        self.step(NormalizerStep::StripAccents)
+    }
+
+    /// Drop control characters other than whitespace
+    pub fn strip_control(self) -> Self {
+        self.step(NormalizerStep::StripControl)
+    }
+
+    /// Replace every numeric character with `with`
+    pub fn replace_digits(self, with: char) -> Self {
+        self.step(NormalizerStep::ReplaceDigits(with))
+    }
+
+    /// Run `text` through every step in order
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to normalize
+    pub fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut steps = self.steps.iter();
+        let mut text = match steps.next() {
+            Some(step) => step.apply(text),
+            None => return Cow::Borrowed(text),
+        };
+        for step in steps {
+            text = step.apply(&text);
+        }
+
+        Cow::Owned(text)
+    }
+
+    /// Normalize text, keeping the span of raw text behind each output byte
+    ///
+    /// The text is always normalized exactly as [`Normalizer::normalize`]
+    /// does. Each character is mapped together with the marks that
+    /// follow it, so every output byte maps to the raw bytes of the
+    /// whole group. Where a rule looks beyond the group, such as the
+    /// final form of Greek sigma, the output it changed maps to the
+    /// raw span of every group it may depend on.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to normalize
+    pub(crate) fn normalize_aligned(&self, text: &str) -> (String, Vec<(usize, usize)>) {
+        if self.steps.is_empty() {
+            return (text.to_owned(), (0..text.len()).map(|at| (at, at + 1)).collect());
+        }
+
+        // Rules that look beyond a group stay within a run of
+        // whitespace or of other text, so each run is settled first.
+        let mut normalized = String::with_capacity(text.len());
+        let mut spans = Vec::with_capacity(text.len());
+        for (start, end) in runs(text) {
+            let (run, run_spans) = self.normalize_groups(text, start, end);
+            let (run, run_spans) = settle(&self.normalize(&text[start..end]), run, run_spans, (start, end));
+            normalized.push_str(&run);
+            spans.extend(run_spans);
+        }
+
+        settle(&self.normalize(text), normalized, spans, (0, text.len()))
+    }
+
+    /// Normalize each character group of `text[start..end]` on its own
+    fn normalize_groups(&self, text: &str, start: usize, end: usize) -> (String, Vec<(usize, usize)>) {
+        let mut normalized = String::with_capacity(end - start);
+        let mut spans = Vec::with_capacity(end - start);
+        let starts = text[start..end].char_indices()
+                                     .filter(|&(at, c)| at > 0 && starts_group(c))
+                                     .map(|(at, _)| start + at)
+                                     .chain(iter::once(end));
+        let mut from = start;
+        for to in starts {
+            let group = self.normalize(&text[from..to]);
+            normalized.push_str(&group);
+            spans.extend(iter::repeat_n((from, to), group.len()));
+            from = to;
+        }
+
+        (normalized, spans)
+    }
+}
+
+/// Split text into runs of whitespace and of other characters
+///
+/// Runs only break where a character group starts.
+fn runs(text: &str) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut space = None;
+    for (at, c) in text.char_indices().filter(|&(_, c)| starts_group(c)) {
+        if space.is_some_and(|space| space != c.is_whitespace()) {
+            runs.push((start, at));
+            start = at;
+        }
+        space = Some(c.is_whitespace());
+    }
+    if start < text.len() {
+        runs.push((start, text.len()));
+    }
+
+    runs
+}
+
+/// Make aligned output agree with the text normalized as a whole
+///
+/// `spans` maps each byte of `aligned` into the raw bytes `bounds`.
+/// Where `aligned` differs from `expected`, the differing stretch of
+/// `expected` maps to the raw span of the groups around it.
+fn settle(expected: &str,
+          aligned: String,
+          spans: Vec<(usize, usize)>,
+          bounds: (usize, usize)) -> (String, Vec<(usize, usize)>) {
+    if aligned == expected {
+        return (aligned, spans);
+    }
+
+    // Shared bytes at either end keep their spans, cut back to the
+    // nearest character and group boundaries.
+    let group_start = |at: usize| at == 0 || at == spans.len() || spans[at - 1] != spans[at];
+    let (a, e) = (aligned.as_bytes(), expected.as_bytes());
+    let mut prefix = a.iter().zip(e).take_while(|(x, y)| x == y).count();
+    while prefix > 0 && !(expected.is_char_boundary(prefix) && group_start(prefix)) {
+        prefix -= 1;
+    }
+    let most = a.len().min(e.len()) - prefix;
+    let mut suffix = a.iter().rev().zip(e.iter().rev()).take(most).take_while(|(x, y)| x == y).count();
+    while suffix > 0 && !(expected.is_char_boundary(e.len() - suffix) && group_start(a.len() - suffix)) {
+        suffix -= 1;
+    }
+
+    let tail = a.len() - suffix;
+    let from = if prefix == 0 { bounds.0 } else { spans[prefix - 1].1 };
+    let to = if tail == a.len() { bounds.1 } else { spans[tail].0 };
+    let mut settled = spans[..prefix].to_vec();
+    settled.extend(iter::repeat_n((from, to.max(from)), e.len() - prefix - suffix));
+    settled.extend_from_slice(&spans[tail..]);
+
+    (expected.to_owned(), settled)
+}
+
+/// Whether normalization never joins `c` to the characters before it
+///
+/// Marks combine with the character they follow, and so do the Hangul
+/// vowel and final consonant jamo.
+fn starts_group(c: char) -> bool {
+    canonical_combining_class(c) == 0 && !is_combining_mark(c) && !('\u{1160}'..='\u{11ff}').contains(&c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forms_unify_composed_and_decomposed_text() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_ne!(composed, decomposed);
+
+        let nfc = Normalizer::new().unicode(UnicodeForm::Nfc);
+        assert_eq!(nfc.normalize(decomposed), composed);
+        let nfd = Normalizer::new().unicode(UnicodeForm::Nfd);
+        assert_eq!(nfd.normalize(composed), decomposed);
+        let nfkc = Normalizer::new().unicode(UnicodeForm::Nfkc);
+        assert_eq!(nfkc.normalize("\u{fb01}x\u{b2}"), "fix2");
+    }
+
+    #[test]
+    fn steps_apply_in_order() {
+        let text = "Ça\u{7}\tVa 2\u{bd}";
+        assert_eq!(Normalizer::default().normalize(text), "ça\u{7}\tva 2\u{bd}");
+        assert_eq!(Normalizer::new().normalize(text), text);
+        assert!(matches!(Normalizer::new().normalize(text), Cow::Borrowed(_)));
+
+        let normalizer = Normalizer::new().strip_control()
+                                          .strip_accents()
+                                          .lowercase()
+                                          .replace_digits('#');
+        assert_eq!(normalizer.normalize(text), "ca\tva ##");
+    }
+
+    #[test]
+    fn names_round_trip() {
+        let normalizer = Normalizer::new().unicode(UnicodeForm::Nfkd)
+                                          .lowercase()
+                                          .strip_accents()
+                                          .strip_control()
+                                          .replace_digits('=');
+        let names = normalizer.names();
+        assert_eq!(names[4], "replace_digits==");
+        assert_eq!(Normalizer::from_names(&names).unwrap(), normalizer);
+
+        for bad in &["upper", "replace_digits", "replace_digits=ab", "NFC"] {
+            assert!(matches!(Normalizer::from_names(&[bad]), Err(Error::Config { .. })), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn aligned_output_maps_back_to_raw_text() {
+        let text = "Cafe\u{301} \u{fb01}X";
+        let normalizer = Normalizer::new().unicode(UnicodeForm::Nfkc).lowercase();
+        let (normalized, spans) = normalizer.normalize_aligned(text);
+        assert_eq!(normalized, normalizer.normalize(text));
+        assert_eq!(normalized, "caf\u{e9} fix");
+        assert_eq!(spans.len(), normalized.len());
+        assert_eq!(spans[3], (3, 6));
+        assert_eq!(spans[4], (3, 6));
+        assert_eq!(&spans[6..9], &[(7, 10), (7, 10), (10, 11)]);
+
+        let (same, identity) = Normalizer::new().normalize_aligned("né");
+        assert_eq!(same, "né");
+        assert_eq!(identity, vec![(0, 1), (1, 2), (2, 3)]);
+    }
+
+    #[test]
+    fn context_rules_see_the_whole_text() {
+        let normalizer = Normalizer::default();
+        let text = "ΟΔΟΣ ΣΟΣ";
+        let (normalized, spans) = normalizer.normalize_aligned(text);
+        assert_eq!(normalized, "οδος σος");
+        assert_eq!(spans.len(), normalized.len());
+        assert_eq!(&spans[..2], &[(0, 2); 2]);
+        assert!(spans[6..8].iter().all(|&span| span == (6, 8)), "{:?}", spans);
+        assert!(spans[9..].iter().all(|&(start, end)| start >= 9 && end <= text.len()));
+    }
+}
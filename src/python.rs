@@ -0,0 +1,968 @@
+//! Python bindings
+//!
+//! Thin PyO3 wrappers over the Rust API. Each Python class owns
+//! the corresponding Rust value and forwards to it, so the extension
+//! module and Rust users share one implementation.
+
+use std::io::{self, ErrorKind, Read};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use numpy::ndarray::{Array, Array1, Array2, Dimension};
+use numpy::IntoPyArray;
+use pyo3::exceptions::{PyFileNotFoundError, PyIOError, PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString};
+
+use crate::builder::{IdOrder, VocabBuilder};
+use crate::encoding::{Encoding, Padding, PaddingSide, Truncation, TruncationStrategy};
+use crate::error::Error;
+use crate::mapped::MappedVocab;
+use crate::model::{BpeTrainer, CharNgrams, Model, UnigramTrainer, WordPieceTrainer};
+use crate::normalizer::Normalizer;
+use crate::template::Template;
+use crate::tokenizer::{
+    DefaultTokenizer, PunctuationTokenizer, RegexTokenizer, SharedTokenizer, SplitDigits, Tokenizer,
+    WhitespaceTokenizer, WordTokenizer,
+};
+use crate::vocab::Vocab;
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        let msg = err.to_string();
+        match err {
+            Error::Io { ref source, .. } if source.kind() == ErrorKind::NotFound => {
+                PyFileNotFoundError::new_err(msg)
+            }
+            Error::Io { .. } => PyIOError::new_err(msg),
+            Error::Threads { .. } => PyRuntimeError::new_err(msg),
+            Error::Pattern { .. }
+            | Error::Config { .. }
+            | Error::Truncation { .. }
+            | Error::Parse { .. }
+            | Error::Corrupt { .. }
+            | Error::DuplicateTerm { .. }
+            | Error::DuplicateId { .. } => PyValueError::new_err(msg),
+        }
+    }
+}
+
+/// Adapts a Python file-like object to [`Read`]
+///
+/// Works with both text and binary files: `str` chunks are encoded
+/// as UTF-8 and `bytes` chunks are passed through.
+struct PyReader<'py> {
+    file: Bound<'py, PyAny>,
+    /// Bytes returned by `file.read` but not yet handed out
+    pending: Vec<u8>,
+}
+
+impl PyReader<'_> {
+    /// Number of units requested per call to `file.read`
+    const CHUNK: usize = 1 << 16;
+}
+
+impl Read for PyReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let to_io = |err: PyErr| io::Error::other(err.to_string());
+            let chunk = self.file.call_method1("read", (PyReader::CHUNK,)).map_err(to_io)?;
+            if let Ok(text) = chunk.cast::<PyString>() {
+                self.pending = text.to_str().map_err(to_io)?.as_bytes().to_vec();
+            } else if let Ok(bytes) = chunk.cast::<PyBytes>() {
+                self.pending = bytes.as_bytes().to_vec();
+            } else {
+                return Err(io::Error::new(ErrorKind::InvalidData, "read() must return str or bytes"));
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+
+        Ok(n)
+    }
+}
+
+/// A Python callable used as a [`Tokenizer`]
+///
+/// The callable takes a `str` and returns a list of `str`. Rust cannot
+/// unwind through [`Tokenizer::tokenize`], so the first exception the
+/// callable raises is kept, the failing chunk yields no terms, and the
+/// exception is re-raised once the calling method returns.
+#[derive(Clone)]
+struct PyTokenizer {
+    func: Arc<Py<PyAny>>,
+    /// First exception raised by `func`, shared by every clone
+    error: Arc<Mutex<Option<PyErr>>>,
+}
+
+impl PyTokenizer {
+    fn new(func: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if !func.is_callable() {
+            return Err(PyTypeError::new_err("tokenizer must be callable"));
+        }
+
+        Ok(PyTokenizer { func: Arc::new(func.clone().unbind()), error: Arc::default() })
+    }
+
+    /// Re-raise the first exception the callable raised, if any
+    fn check(&self) -> PyResult<()> {
+        match self.error.lock().unwrap().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn call(&self, py: Python<'_>, text: &str) -> PyResult<Vec<String>> {
+        let terms = self.func.bind(py).call1((text,))?;
+        if terms.is_instance_of::<PyString>() {
+            return Err(PyTypeError::new_err("tokenizer must return a list of str, not str"));
+        }
+
+        terms.extract()
+    }
+}
+
+impl Tokenizer for PyTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        Python::attach(|py| {
+            self.call(py, text).unwrap_or_else(|err| {
+                self.error.lock().unwrap().get_or_insert(err);
+                Vec::new()
+            })
+        })
+    }
+}
+
+/// Configure a [`VocabBuilder`] from Python keyword arguments
+///
+/// * `specials` - special tokens reserved at the lowest ids
+/// * `unk` - token used for out-of-vocabulary terms
+/// * `min_freq` - drop terms seen fewer times than this
+/// * `max_size` - cap on the vocabulary size, specials included
+/// * `order` - `"first_seen"` or `"frequency"`
+/// * `threads` - threads used to count files, 0 for one per core
+/// * `normalizer` - list of normalizer step names, `[]` to keep text as is
+/// * `tokenizer` - `"default"`, `"whitespace"`, `"punctuation"`, `"words"`,
+///   `"gpt2"`, or a callable splitting a `str` into a list of terms
+/// * `pattern` - regular expression matching one term, instead of `tokenizer`
+/// * `split_digits` - make every digit of a term its own term
+/// * `model` - `"word"`, `"chars"` or `"char_ngrams"`, the entries counted
+/// * `ngram_range` - shortest and longest `"char_ngrams"` length, `(3, 6)`
+///
+/// The Python tokenizer, if one was given, is returned alongside so
+/// its exceptions can be raised after the build.
+fn builder(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<(VocabBuilder, Option<PyTokenizer>)> {
+    const KEYS: [&str; 12] = [
+        "specials", "unk", "min_freq", "max_size", "order", "threads", "normalizer", "tokenizer",
+        "pattern", "split_digits", "model", "ngram_range",
+    ];
+
+    let mut builder = Vocab::builder();
+    let kwargs = match kwargs {
+        Some(kwargs) => kwargs,
+        None => return Ok((builder, None)),
+    };
+    for key in kwargs.keys() {
+        let key: String = key.extract()?;
+        if !KEYS.contains(&key.as_str()) {
+            return Err(PyTypeError::new_err(format!("unexpected keyword argument {:?}", key)));
+        }
+    }
+
+    // Specials are applied before `unk` so their ids follow the list
+    // regardless of keyword order.
+    let arg = |key: &str| -> PyResult<_> { Ok(kwargs.get_item(key)?.filter(|value| !value.is_none())) };
+    if let Some(specials) = arg("specials")? {
+        builder = builder.special_tokens(&specials.extract::<Vec<String>>()?);
+    }
+    if let Some(unk) = arg("unk")? {
+        builder = builder.unk_token(unk.extract()?);
+    }
+    if let Some(min_freq) = arg("min_freq")? {
+        builder = builder.min_freq(min_freq.extract()?);
+    }
+    if let Some(max_size) = arg("max_size")? {
+        builder = builder.max_size(max_size.extract()?);
+    }
+    if let Some(order) = arg("order")? {
+        builder = match order.extract::<String>()?.as_str() {
+            "first_seen" => builder.order(IdOrder::FirstSeen),
+            "frequency" => builder.order(IdOrder::Frequency),
+            order => return Err(PyValueError::new_err(format!("unknown order {:?}", order))),
+        };
+    }
+    if let Some(threads) = arg("threads")? {
+        builder = builder.threads(threads.extract()?);
+    }
+    if let Some(normalizer) = arg("normalizer")? {
+        builder = builder.normalizer(Normalizer::from_names(&normalizer.extract::<Vec<String>>()?)?);
+    }
+    let ngrams = match arg("ngram_range")? {
+        Some(range) => {
+            let (min_n, max_n) = range.extract()?;
+            CharNgrams::new(min_n, max_n)
+        }
+        None => CharNgrams::default(),
+    };
+    if let Some(model) = arg("model")? {
+        builder = match model.extract::<String>()?.as_str() {
+            "word" => builder.model(Model::Word),
+            "chars" => builder.model(Model::Chars),
+            "char_ngrams" => builder.model(Model::CharNgrams(ngrams)),
+            model => return Err(PyValueError::new_err(format!("unknown model {:?}", model))),
+        };
+    }
+
+    let mut callable = None;
+    let mut tokenizer = match (arg("tokenizer")?, arg("pattern")?) {
+        (Some(_), Some(_)) => return Err(PyTypeError::new_err("pass either tokenizer or pattern")),
+        (Some(name), None) if name.is_instance_of::<PyString>() => named_tokenizer(&name.extract::<String>()?)?,
+        (Some(func), None) => {
+            let func = PyTokenizer::new(&func)?;
+            callable = Some(func.clone());
+            SharedTokenizer::new(func)
+        }
+        (None, Some(pattern)) => SharedTokenizer::new(RegexTokenizer::new(&pattern.extract::<String>()?)?),
+        (None, None) => SharedTokenizer::default(),
+    };
+    if arg("split_digits")?.map(|split| split.is_truthy()).transpose()? == Some(true) {
+        tokenizer = SharedTokenizer::new(SplitDigits(tokenizer));
+    }
+
+    Ok((builder.tokenizer(tokenizer), callable))
+}
+
+/// Look up a built-in tokenizer by name
+fn named_tokenizer(name: &str) -> PyResult<SharedTokenizer> {
+    let tokenizer = match name {
+        "default" => SharedTokenizer::new(DefaultTokenizer),
+        "whitespace" => SharedTokenizer::new(WhitespaceTokenizer),
+        "punctuation" => SharedTokenizer::new(PunctuationTokenizer),
+        "words" => SharedTokenizer::new(WordTokenizer),
+        "gpt2" => SharedTokenizer::new(RegexTokenizer::gpt2()),
+        name => return Err(PyValueError::new_err(format!("unknown tokenizer {:?}", name))),
+    };
+
+    Ok(tokenizer)
+}
+
+/// Element type of the NumPy arrays of token ids
+#[derive(Debug, Clone, Copy)]
+enum IdType {
+    Int32,
+    Int64,
+}
+
+impl IdType {
+    /// Look up an element type by its NumPy name
+    fn parse(dtype: &str) -> PyResult<Self> {
+        match dtype {
+            "int32" => Ok(IdType::Int32),
+            "int64" => Ok(IdType::Int64),
+            dtype => Err(PyValueError::new_err(format!("unknown dtype {:?}", dtype))),
+        }
+    }
+
+    /// Hand `ids` over to NumPy, copying them only to widen them
+    fn array<'py, D: Dimension>(self, py: Python<'py>, ids: Array<i32, D>) -> Bound<'py, PyAny> {
+        match self {
+            IdType::Int32 => ids.into_pyarray(py).into_any(),
+            IdType::Int64 => ids.mapv(i64::from).into_pyarray(py).into_any(),
+        }
+    }
+}
+
+/// Convert the ids of a batch to NumPy
+///
+/// Encodings padded to one length become the rows of a 2-D array;
+/// otherwise each becomes its own 1-D array in a list.
+fn batch_ids<'py>(py: Python<'py>,
+                  encodings: &[Encoding],
+                  padded: bool,
+                  dtype: IdType) -> PyResult<Bound<'py, PyAny>> {
+    let width = encodings.first().map_or(0, Encoding::len);
+    if padded && encodings.iter().all(|encoding| encoding.len() == width) {
+        let mut ids = Vec::with_capacity(encodings.len() * width);
+        for encoding in encodings {
+            ids.extend_from_slice(encoding.ids());
+        }
+        let ids = Array2::from_shape_vec((encodings.len(), width), ids).expect("every row has the same length");
+        return Ok(dtype.array(py, ids));
+    }
+
+    let rows = encodings.iter().map(|encoding| dtype.array(py, Array1::from(encoding.ids().to_vec())));
+    Ok(PyList::new(py, rows)?.into_any())
+}
+
+/// Vocabulary for NLP applications
+///
+/// This is a mapping from tokenized
+/// vocabulary terms to integer tokens.
+#[pyclass(name = "Vocab")]
+pub struct PyVocab {
+    inner: Vocab,
+    /// Python tokenizer the vocabulary was built with, if any
+    tokenizer: Option<PyTokenizer>,
+}
+
+impl PyVocab {
+    /// Wrap a vocabulary that uses the default tokenizer
+    fn wrap(inner: Vocab) -> Self {
+        PyVocab { inner, tokenizer: None }
+    }
+
+    /// Configure a builder from `kwargs` and run `build` with it
+    fn build<F>(kwargs: Option<&Bound<'_, PyDict>>, build: F) -> PyResult<Self>
+    where
+        F: FnOnce(VocabBuilder) -> crate::Result<Vocab>,
+    {
+        let (builder, tokenizer) = builder(kwargs)?;
+        let inner = build(builder);
+        if let Some(tokenizer) = &tokenizer {
+            tokenizer.check()?;
+        }
+
+        Ok(PyVocab { inner: inner?, tokenizer })
+    }
+
+    /// Raise any exception the Python tokenizer hit while encoding
+    fn check(&self) -> PyResult<()> {
+        match &self.tokenizer {
+            Some(tokenizer) => tokenizer.check(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[pymethods]
+impl PyVocab {
+    /// Create a Vocabulary
+    ///
+    /// Keyword arguments configure the build, see [`builder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a raw text file to be parsed
+    #[new]
+    #[pyo3(signature = (fpath, **kwargs))]
+    pub fn new(py: Python<'_>, fpath: &str, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        PyVocab::build(kwargs, |builder| py.detach(|| builder.build(fpath)))
+    }
+
+    /// Create a Vocabulary from a file-like object, such as `sys.stdin`
+    ///
+    /// The object is read in chunks, so the whole corpus is never
+    /// held in memory.
+    #[staticmethod]
+    #[pyo3(signature = (file, **kwargs))]
+    pub fn from_reader(file: &Bound<'_, PyAny>, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let reader = PyReader { file: file.clone(), pending: Vec::new() };
+        PyVocab::build(kwargs, |builder| builder.build_reader(reader))
+    }
+
+    /// Create a Vocabulary from several raw text files
+    #[staticmethod]
+    #[pyo3(signature = (paths, **kwargs))]
+    pub fn from_files(py: Python<'_>, paths: Vec<String>, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        PyVocab::build(kwargs, |builder| py.detach(|| builder.build_files(&paths)))
+    }
+
+    /// Create a Vocabulary from every file in a directory
+    #[staticmethod]
+    #[pyo3(signature = (dir, recursive = false, **kwargs))]
+    pub fn from_dir(py: Python<'_>,
+                    dir: &str,
+                    recursive: bool,
+                    kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        PyVocab::build(kwargs, |builder| py.detach(|| builder.build_dir(dir, recursive)))
+    }
+
+    /// Create a Vocabulary from every file matching a glob pattern
+    #[staticmethod]
+    #[pyo3(signature = (pattern, **kwargs))]
+    pub fn from_glob(py: Python<'_>, pattern: &str, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        PyVocab::build(kwargs, |builder| py.detach(|| builder.build_glob(pattern)))
+    }
+
+    /// Read in a file
+    #[staticmethod]
+    pub fn read_file(fpath: &str) -> PyResult<String> {
+        Ok(Vocab::read_file(fpath)?)
+    }
+
+    /// Tokenize raw text
+    #[staticmethod]
+    pub fn tokenize(text: String) -> Vec<String> {
+        Vocab::tokenize(text)
+    }
+
+    /// Load a previously built vocabulary from disk
+    #[staticmethod]
+    pub fn load(fpath: &str) -> PyResult<Self> {
+        Ok(PyVocab::wrap(Vocab::load(fpath)?))
+    }
+
+    /// Load a vocabulary from a JSON file
+    #[staticmethod]
+    pub fn load_json(fpath: &str) -> PyResult<Self> {
+        Ok(PyVocab::wrap(Vocab::load_json(fpath)?))
+    }
+
+    /// Parse a vocabulary from a JSON object of terms to ids
+    #[staticmethod]
+    pub fn from_json(json: &str) -> PyResult<Self> {
+        Ok(PyVocab::wrap(Vocab::from_json(json)?))
+    }
+
+    /// Write the vocabulary to disk
+    ///
+    /// Built-in tokenizers are saved with it. A Python callable is not,
+    /// and the loaded vocabulary falls back to the default tokenizer.
+    pub fn write(&self, fpath: &str) -> PyResult<()> {
+        Ok(self.inner.write(fpath)?)
+    }
+
+    /// Load a vocabulary from a BERT `vocab.txt` file
+    #[staticmethod]
+    pub fn load_bert(fpath: &str) -> PyResult<Self> {
+        Ok(PyVocab::wrap(Vocab::load_bert(fpath)?))
+    }
+
+    /// Write the vocabulary to disk as a BERT `vocab.txt`
+    pub fn write_bert(&self, fpath: &str) -> PyResult<()> {
+        Ok(self.inner.write_bert(fpath)?)
+    }
+
+    /// Load a binary vocabulary into memory
+    #[staticmethod]
+    pub fn load_binary(fpath: &str) -> PyResult<Self> {
+        Ok(PyVocab::wrap(Vocab::load_binary(fpath)?))
+    }
+
+    /// Write the vocabulary to disk in the binary format
+    pub fn write_binary(&self, fpath: &str) -> PyResult<()> {
+        Ok(self.inner.write_binary(fpath)?)
+    }
+
+    /// Write the vocabulary to disk as JSON
+    pub fn write_json(&self, fpath: &str) -> PyResult<()> {
+        Ok(self.inner.write_json(fpath)?)
+    }
+
+    /// Render the vocabulary as a JSON object of terms to ids
+    pub fn to_json(&self) -> String {
+        self.inner.to_json()
+    }
+
+    /// Get the names of the normalizer steps applied before tokenizing
+    pub fn normalizer(&self) -> Vec<String> {
+        self.inner.normalizer().names()
+    }
+
+    /// Learn a byte-pair encoding vocabulary from this one's frequencies
+    ///
+    /// # Arguments
+    ///
+    /// * `vocab_size` - target number of entries, special tokens included
+    /// * `min_frequency` - pairs seen fewer times are never merged
+    /// * `suffix` - end-of-word marker, such as `</w>`
+    /// * `byte_level` - learn merges over UTF-8 bytes, so any text encodes
+    #[pyo3(signature = (vocab_size, min_frequency = 2, suffix = None, byte_level = false))]
+    pub fn train_bpe(&self,
+                     py: Python<'_>,
+                     vocab_size: usize,
+                     min_frequency: u64,
+                     suffix: Option<&str>,
+                     byte_level: bool) -> PyResult<Self> {
+        let mut trainer = BpeTrainer::new(vocab_size).min_frequency(min_frequency);
+        if let Some(suffix) = suffix {
+            trainer = trainer.suffix(suffix);
+        }
+        if byte_level {
+            trainer = trainer.byte_level();
+        }
+        let inner = py.detach(|| trainer.train(&self.inner))?;
+
+        Ok(PyVocab { inner, tokenizer: self.tokenizer.clone() })
+    }
+
+    /// Learn a Unigram language model vocabulary from this one's frequencies
+    ///
+    /// # Arguments
+    ///
+    /// * `vocab_size` - target number of entries, special tokens included
+    /// * `max_piece_chars` - longest piece, in characters
+    /// * `shrinking_factor` - share of pieces kept by each pruning round
+    #[pyo3(signature = (vocab_size, max_piece_chars = 16, shrinking_factor = 0.75))]
+    pub fn train_unigram(&self,
+                         py: Python<'_>,
+                         vocab_size: usize,
+                         max_piece_chars: usize,
+                         shrinking_factor: f64) -> PyResult<Self> {
+        let trainer = UnigramTrainer::new(vocab_size).max_piece_chars(max_piece_chars)
+                                                     .shrinking_factor(shrinking_factor);
+        let inner = py.detach(|| trainer.train(&self.inner))?;
+
+        Ok(PyVocab { inner, tokenizer: self.tokenizer.clone() })
+    }
+
+    /// Learn a WordPiece vocabulary from this one's frequencies
+    ///
+    /// # Arguments
+    ///
+    /// * `vocab_size` - target number of entries, special tokens included
+    /// * `min_frequency` - pairs seen fewer times are never merged
+    /// * `prefix` - marks pieces that continue a word
+    /// * `max_chars` - longer words encode as the unknown token
+    #[pyo3(signature = (vocab_size, min_frequency = 2, prefix = "##", max_chars = 100))]
+    pub fn train_wordpiece(&self,
+                           py: Python<'_>,
+                           vocab_size: usize,
+                           min_frequency: u64,
+                           prefix: &str,
+                           max_chars: usize) -> PyResult<Self> {
+        let trainer = WordPieceTrainer::new(vocab_size).min_frequency(min_frequency)
+                                                       .prefix(prefix)
+                                                       .max_chars(max_chars);
+        let inner = py.detach(|| trainer.train(&self.inner))?;
+
+        Ok(PyVocab { inner, tokenizer: self.tokenizer.clone() })
+    }
+
+    /// Get the number of vocabulary terms
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Get every term with its token, sorted by token
+    pub fn entries(&self) -> Vec<(String, i32)> {
+        self.inner.entries()
+                  .into_iter()
+                  .map(|(voc, tok)| (voc.to_owned(), tok))
+                  .collect()
+    }
+
+    /// Get the number of times a term was seen while building
+    pub fn frequency(&self, term: &str) -> u64 {
+        self.inner.frequency(term)
+    }
+
+    /// Get the `n` most frequent terms with their counts
+    pub fn most_common(&self, n: usize) -> Vec<(String, u64)> {
+        self.inner.most_common(n)
+                  .into_iter()
+                  .map(|(voc, count)| (voc.to_owned(), count))
+                  .collect()
+    }
+
+    /// Get the reserved special tokens, in id order
+    pub fn special_tokens(&self) -> Vec<String> {
+        self.inner.special_tokens().to_vec()
+    }
+
+    /// Check whether a term is a reserved special token
+    pub fn is_special(&self, term: &str) -> bool {
+        self.inner.is_special(term)
+    }
+
+    /// Get the id used for out-of-vocabulary terms, if any
+    pub fn unk_id(&self) -> Option<i32> {
+        self.inner.unk_id()
+    }
+
+    /// Look up the token id of a vocabulary term
+    pub fn token_to_id(&self, term: &str) -> Option<i32> {
+        self.inner.token_to_id(term)
+    }
+
+    /// Look up the vocabulary term for a token id
+    pub fn id_to_token(&self, id: i32) -> Option<String> {
+        self.inner.id_to_token(id).map(|s| s.to_owned())
+    }
+
+    /// Encode raw text as a NumPy array of token ids
+    ///
+    /// Text is split with the tokenizer the vocabulary was built with.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to encode
+    /// * `dtype` - `"int32"` or `"int64"`
+    #[pyo3(signature = (text, dtype = "int32"))]
+    pub fn encode<'py>(&self, py: Python<'py>, text: &str, dtype: &str) -> PyResult<Bound<'py, PyAny>> {
+        let dtype = IdType::parse(dtype)?;
+        let ids = self.inner.encode(text);
+        self.check()?;
+
+        Ok(dtype.array(py, Array1::from(ids)))
+    }
+
+    /// Encode raw text, keeping the span of text behind every token
+    pub fn encode_with_offsets(&self, text: &str) -> PyResult<PyEncoding> {
+        let inner = self.inner.encode_with_offsets(text);
+        self.check()?;
+
+        Ok(PyEncoding { inner: inner? })
+    }
+
+    /// Encode many texts in parallel as NumPy arrays of token ids
+    ///
+    /// Texts are truncated as configured. With padding enabled the
+    /// result is one 2-D array with a row per text, otherwise a list of
+    /// 1-D arrays.
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - raw texts to encode
+    /// * `dtype` - `"int32"` or `"int64"`
+    #[pyo3(signature = (texts, dtype = "int32"))]
+    pub fn encode_batch<'py>(&self,
+                             py: Python<'py>,
+                             texts: Vec<String>,
+                             dtype: &str) -> PyResult<Bound<'py, PyAny>> {
+        let dtype = IdType::parse(dtype)?;
+        let encodings = py.detach(|| self.inner.encode_batch(&texts));
+        self.check()?;
+
+        batch_ids(py, &encodings?, self.inner.padding().is_some(), dtype)
+    }
+
+    /// Encode many texts in parallel, padded and truncated as configured
+    pub fn encode_batch_with_offsets(&self, py: Python<'_>, texts: Vec<String>) -> PyResult<Vec<PyEncoding>> {
+        let encodings = py.detach(|| self.inner.encode_batch(&texts));
+        self.check()?;
+
+        Ok(encodings?.into_iter().map(|inner| PyEncoding { inner }).collect())
+    }
+
+    /// Encode a pair of texts, joined by the pair template
+    pub fn encode_pair(&self, first: &str, second: &str) -> PyResult<PyEncoding> {
+        let inner = self.inner.encode_pair(first, second);
+        self.check()?;
+
+        Ok(PyEncoding { inner: inner? })
+    }
+
+    /// Encode many pairs of texts in parallel, padded and truncated as configured
+    pub fn encode_pair_batch(&self, py: Python<'_>, pairs: Vec<(String, String)>) -> PyResult<Vec<PyEncoding>> {
+        let encodings = py.detach(|| self.inner.encode_pair_batch(&pairs));
+        self.check()?;
+
+        Ok(encodings?.into_iter().map(|inner| PyEncoding { inner }).collect())
+    }
+
+    /// Place special tokens around every encoding, saved with the vocabulary
+    ///
+    /// # Arguments
+    ///
+    /// * `single` - template for one text, using `$A`
+    /// * `pair` - template for a pair, using `$A` and `$B`
+    #[pyo3(signature = (single = "[CLS] $A [SEP]", pair = "[CLS] $A [SEP] $B [SEP]"))]
+    pub fn set_template(&mut self, single: &str, pair: &str) -> PyResult<()> {
+        let template = Template::new(single, pair)?;
+        template.check(|token| self.inner.token_to_id(token).is_some())?;
+        self.inner = mem::take(&mut self.inner).with_template(template)?;
+
+        Ok(())
+    }
+
+    /// Get the single and pair templates, if there are any
+    pub fn template(&self) -> Option<(String, String)> {
+        self.inner.template().map(|template| (template.single(), template.pair()))
+    }
+
+    /// Stop placing special tokens around encodings
+    pub fn no_template(&mut self) {
+        self.inner = mem::take(&mut self.inner).without_template();
+    }
+
+    /// Pad the encodings of each batch to one length
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - tokens to pad to, or `None` for the longest encoding
+    /// * `multiple_of` - round the length up to a multiple of this
+    /// * `side` - `"right"` or `"left"`
+    /// * `token` - entry whose id pads
+    #[pyo3(signature = (length = None, multiple_of = None, side = "right", token = "<pad>"))]
+    pub fn enable_padding(&mut self,
+                          length: Option<usize>,
+                          multiple_of: Option<usize>,
+                          side: &str,
+                          token: &str) -> PyResult<()> {
+        let side = match side {
+            "left" => PaddingSide::Left,
+            "right" => PaddingSide::Right,
+            side => return Err(PyValueError::new_err(format!("unknown padding side {:?}", side))),
+        };
+        let mut padding = match length {
+            Some(length) => Padding::fixed(length),
+            None => Padding::longest(),
+        };
+        if let Some(multiple_of) = multiple_of {
+            padding = padding.with_multiple_of(multiple_of);
+        }
+        self.inner = mem::take(&mut self.inner).with_padding(padding.with_side(side).with_token(token));
+
+        Ok(())
+    }
+
+    /// Stop padding batches
+    pub fn no_padding(&mut self) {
+        self.inner = mem::take(&mut self.inner).without_padding();
+    }
+
+    /// Cut encodings down to a maximum length
+    ///
+    /// # Arguments
+    ///
+    /// * `max_length` - most tokens to keep
+    /// * `stride` - tokens each overflowing window repeats from the last
+    /// * `strategy` - `"longest_first"`, `"only_first"` or `"only_second"`
+    #[pyo3(signature = (max_length, stride = 0, strategy = "longest_first"))]
+    pub fn enable_truncation(&mut self, max_length: usize, stride: usize, strategy: &str) -> PyResult<()> {
+        let strategy = match strategy {
+            "longest_first" => TruncationStrategy::LongestFirst,
+            "only_first" => TruncationStrategy::OnlyFirst,
+            "only_second" => TruncationStrategy::OnlySecond,
+            strategy => return Err(PyValueError::new_err(format!("unknown truncation strategy {:?}", strategy))),
+        };
+        let truncation = Truncation::new(max_length).with_stride(stride).with_strategy(strategy);
+        self.inner = mem::take(&mut self.inner).with_truncation(truncation);
+
+        Ok(())
+    }
+
+    /// Stop truncating encodings
+    pub fn no_truncation(&mut self) {
+        self.inner = mem::take(&mut self.inner).without_truncation();
+    }
+
+    /// Encode raw text in the `n` most probable ways, best first
+    pub fn encode_nbest(&self, text: &str, n: usize) -> PyResult<Vec<Vec<i32>>> {
+        let nbest = self.inner.encode_nbest(text, n);
+        self.check()?;
+
+        Ok(nbest)
+    }
+
+    /// Encode raw text with a randomly drawn split of each term
+    #[pyo3(signature = (text, alpha = 0.1, seed = 0))]
+    pub fn encode_sample(&self, text: &str, alpha: f64, seed: u64) -> PyResult<Vec<i32>> {
+        let ids = self.inner.encode_sample(text, alpha, seed);
+        self.check()?;
+
+        Ok(ids)
+    }
+
+    /// Encode already tokenized terms as token ids
+    pub fn encode_tokens(&self, tokens: Vec<String>) -> Vec<i32> {
+        self.inner.encode_tokens(&tokens)
+    }
+
+    /// Decode token ids back into vocabulary terms
+    pub fn decode(&self, ids: Vec<i32>) -> Vec<String> {
+        self.inner.decode(&ids)
+    }
+
+    /// Decode token ids back into the bytes they stand for
+    pub fn decode_bytes<'py>(&self, py: Python<'py>, ids: Vec<i32>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.decode_bytes(&ids))
+    }
+}
+
+/// A binary vocabulary queried in place through a memory map
+#[pyclass(name = "MappedVocab")]
+pub struct PyMappedVocab {
+    inner: MappedVocab,
+}
+
+#[pymethods]
+impl PyMappedVocab {
+    /// Map a binary vocabulary file, checking only its header
+    #[new]
+    pub fn new(fpath: &str) -> PyResult<Self> {
+        Ok(PyMappedVocab { inner: MappedVocab::open(fpath)? })
+    }
+
+    /// Check the checksum and every entry of the mapped file
+    pub fn verify(&self) -> PyResult<()> {
+        Ok(self.inner.verify()?)
+    }
+
+    /// Get the names of the normalizer steps saved with the vocabulary
+    pub fn normalizer(&self) -> Vec<String> {
+        self.inner.normalizer().names()
+    }
+
+    /// Get the number of vocabulary terms
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Look up the token id of a vocabulary term
+    pub fn token_to_id(&self, term: &str) -> Option<i32> {
+        self.inner.token_to_id(term)
+    }
+
+    /// Look up the vocabulary term for a token id
+    pub fn id_to_token(&self, id: i32) -> Option<String> {
+        self.inner.id_to_token(id).map(|s| s.to_owned())
+    }
+
+    /// Check whether a term is a reserved special token
+    pub fn is_special(&self, term: &str) -> bool {
+        self.inner.is_special(term)
+    }
+
+    /// Get the id used for out-of-vocabulary terms, if any
+    pub fn unk_id(&self) -> Option<i32> {
+        self.inner.unk_id()
+    }
+
+    /// Encode raw text as a NumPy array of token ids
+    #[pyo3(signature = (text, dtype = "int32"))]
+    pub fn encode<'py>(&self, py: Python<'py>, text: &str, dtype: &str) -> PyResult<Bound<'py, PyAny>> {
+        Ok(IdType::parse(dtype)?.array(py, Array1::from(self.inner.encode(text))))
+    }
+
+    /// Encode raw text, keeping the span of text behind every token
+    pub fn encode_with_offsets(&self, text: &str) -> PyResult<PyEncoding> {
+        Ok(PyEncoding { inner: self.inner.encode_with_offsets(text)? })
+    }
+
+    /// Encode a pair of texts, joined by the saved pair template
+    pub fn encode_pair(&self, first: &str, second: &str) -> PyResult<PyEncoding> {
+        Ok(PyEncoding { inner: self.inner.encode_pair(first, second)? })
+    }
+
+    /// Encode raw text in the `n` most probable ways, best first
+    pub fn encode_nbest(&self, text: &str, n: usize) -> Vec<Vec<i32>> {
+        self.inner.encode_nbest(text, n)
+    }
+
+    /// Encode raw text with a randomly drawn split of each term
+    #[pyo3(signature = (text, alpha = 0.1, seed = 0))]
+    pub fn encode_sample(&self, text: &str, alpha: f64, seed: u64) -> Vec<i32> {
+        self.inner.encode_sample(text, alpha, seed)
+    }
+
+    /// Encode already tokenized terms as token ids
+    pub fn encode_tokens(&self, tokens: Vec<String>) -> Vec<i32> {
+        self.inner.encode_tokens(&tokens)
+    }
+
+    /// Decode token ids back into vocabulary terms
+    pub fn decode(&self, ids: Vec<i32>) -> Vec<String> {
+        self.inner.decode(&ids)
+    }
+
+    /// Decode token ids back into the bytes they stand for
+    pub fn decode_bytes<'py>(&self, py: Python<'py>, ids: Vec<i32>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.decode_bytes(&ids))
+    }
+
+    /// Copy the whole vocabulary into an owned `Vocab`
+    pub fn to_vocab(&self) -> PyResult<PyVocab> {
+        Ok(PyVocab::wrap(self.inner.to_vocab()?))
+    }
+}
+
+/// Token ids of a text, with what a model needs alongside them
+///
+/// Offsets are character offsets, so they slice the Python `str` that
+/// was encoded; `byte_offsets` index its UTF-8 encoding instead.
+#[pyclass(name = "Encoding")]
+pub struct PyEncoding {
+    inner: Encoding,
+}
+
+#[pymethods]
+impl PyEncoding {
+    /// Get the token ids
+    #[getter]
+    pub fn ids(&self) -> Vec<i32> {
+        self.inner.ids().to_vec()
+    }
+
+    /// Get the vocabulary entry of each token
+    #[getter]
+    pub fn tokens(&self) -> Vec<String> {
+        self.inner.tokens().to_vec()
+    }
+
+    /// Get the character span of the text behind each token
+    #[getter]
+    pub fn offsets(&self) -> Vec<(usize, usize)> {
+        self.inner.char_offsets().to_vec()
+    }
+
+    /// Get the UTF-8 byte span of the text behind each token
+    #[getter]
+    pub fn byte_offsets(&self) -> Vec<(usize, usize)> {
+        self.inner.offsets().to_vec()
+    }
+
+    /// Get the index of the tokenizer term each token was split from
+    #[getter]
+    pub fn word_ids(&self) -> Vec<Option<usize>> {
+        self.inner.word_ids().to_vec()
+    }
+
+    /// Get which text of a pair each token came from, or `None`
+    #[getter]
+    pub fn sequence_ids(&self) -> Vec<Option<usize>> {
+        self.inner.sequence_ids().to_vec()
+    }
+
+    /// Get the segment of each token
+    #[getter]
+    pub fn type_ids(&self) -> Vec<u32> {
+        self.inner.type_ids().to_vec()
+    }
+
+    /// Get 1 for every token a model should attend to and 0 for padding
+    #[getter]
+    pub fn attention_mask(&self) -> Vec<u32> {
+        self.inner.attention_mask().to_vec()
+    }
+
+    /// Get 1 for every special token and 0 for the rest
+    #[getter]
+    pub fn special_tokens_mask(&self) -> Vec<u32> {
+        self.inner.special_tokens_mask().to_vec()
+    }
+
+    /// Get the windows of tokens that truncation cut off
+    #[getter]
+    pub fn overflowing(&self) -> Vec<PyEncoding> {
+        self.inner.overflowing()
+                  .iter()
+                  .map(|inner| PyEncoding { inner: inner.clone() })
+                  .collect()
+    }
+
+    /// Find the first token covering a character of the text
+    pub fn char_to_token(&self, pos: usize) -> Option<usize> {
+        self.inner.char_to_token(pos)
+    }
+
+    /// Get the `(start, end)` range of tokens split from a tokenizer term
+    pub fn word_to_tokens(&self, word: usize) -> Option<(usize, usize)> {
+        self.inner.word_to_tokens(word)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+#[pymodule]
+fn tok(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyVocab>()?;
+    m.add_class::<PyMappedVocab>()?;
+    m.add_class::<PyEncoding>()?;
+    Ok(())
+}
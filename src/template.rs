@@ -0,0 +1,193 @@
+//! Post-processing templates
+//!
+//! Models such as BERT expect special tokens around the tokens of a
+//! text, and between the two texts of a pair. A [`Template`] says
+//! where they go and which segment each token is in, and is saved with
+//! the vocabulary so loaded vocabularies encode the same way.
+
+use serde_json::{json, Value};
+
+use crate::error::{Error, Result};
+
+/// One item of a template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Item {
+    /// The tokens of the first text, 0, or the second, 1
+    Sequence { index: usize, type_id: u32 },
+    /// A vocabulary entry inserted as is
+    Special { token: String, type_id: u32 },
+}
+
+/// Where special tokens go around encoded texts
+///
+/// A template is written as items separated by spaces. `$A` and `$B`
+/// stand for the tokens of the first and second text, and any other
+/// item is a vocabulary entry to insert. An item may end in `:n` to
+/// set its type id; otherwise `$A` is in segment 0, `$B` in segment 1,
+/// and inserted entries in the segment of the text before them.
+///
+/// ```
+/// use tok::Template;
+///
+/// let bert = Template::new("[CLS] $A [SEP]", "[CLS] $A [SEP] $B [SEP]")?;
+/// assert_eq!(bert, Template::bert());
+/// assert_eq!(bert.pair(), "[CLS]:0 $A:0 [SEP]:0 $B:1 [SEP]:1");
+/// # Ok::<(), tok::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    single: Vec<Item>,
+    pair: Vec<Item>,
+}
+
+impl Template {
+    /// Parse the templates for a single text and for a pair
+    ///
+    /// # Arguments
+    ///
+    /// * `single` - template using `$A` once, such as `[CLS] $A [SEP]`
+    /// * `pair` - template using `$A` and `$B` once each
+    pub fn new(single: &str, pair: &str) -> Result<Self> {
+        Ok(Template { single: parse(single, 1)?, pair: parse(pair, 2)? })
+    }
+
+    /// The template of BERT, `[CLS] $A [SEP]` and `[CLS] $A [SEP] $B [SEP]`
+    pub fn bert() -> Self {
+        Template::new("[CLS] $A [SEP]", "[CLS] $A [SEP] $B [SEP]").expect("the BERT template parses")
+    }
+
+    /// Get the template for a single text, with every type id written out
+    pub fn single(&self) -> String {
+        render(&self.single)
+    }
+
+    /// Get the template for a pair, with every type id written out
+    pub fn pair(&self) -> String {
+        render(&self.pair)
+    }
+
+    /// Get the items placed around one text, or a pair
+    pub(crate) fn items(&self, pair: bool) -> &[Item] {
+        if pair {
+            &self.pair
+        } else {
+            &self.single
+        }
+    }
+
+    /// Get every entry the template inserts
+    pub(crate) fn tokens(&self) -> impl Iterator<Item = &str> {
+        self.single.iter().chain(&self.pair).filter_map(|item| match item {
+            Item::Special { token, .. } => Some(token.as_str()),
+            Item::Sequence { .. } => None,
+        })
+    }
+
+    /// Check that `known` accepts every entry the template inserts
+    pub(crate) fn check<F: Fn(&str) -> bool>(&self, known: F) -> Result<()> {
+        match self.tokens().find(|token| !known(token)) {
+            Some(token) => Err(Error::Config {
+                reason: format!("template token {:?} is not in the vocabulary", token),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Count the entries inserted around one text, or a pair
+    pub(crate) fn added_tokens(&self, pair: bool) -> usize {
+        self.items(pair).iter().filter(|item| matches!(item, Item::Special { .. })).count()
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({ "single": self.single(), "pair": self.pair() })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Template> {
+        let template = |key| {
+            value.get(key)
+                 .and_then(Value::as_str)
+                 .ok_or_else(|| config(format!("{} template is not a string", key)))
+        };
+
+        Template::new(template("single")?, template("pair")?)
+    }
+}
+
+/// Parse a template for `sequences` texts
+fn parse(template: &str, sequences: usize) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    let mut seen = vec![false; sequences];
+    let mut segment = 0;
+    for word in template.split_whitespace() {
+        let (name, type_id) = match word.rsplit_once(':') {
+            Some((name, type_id)) if !name.is_empty() => match type_id.parse() {
+                Ok(type_id) => (name, Some(type_id)),
+                Err(_) => (word, None),
+            },
+            _ => (word, None),
+        };
+        let index = match name {
+            "$A" => Some(0),
+            "$B" => Some(1),
+            _ => None,
+        };
+        let item = match index {
+            Some(index) if index >= sequences || seen[index] => {
+                return Err(config(format!("{} is not expected in {:?}", name, template)))
+            }
+            Some(index) => {
+                seen[index] = true;
+                segment = type_id.unwrap_or(index as u32);
+                Item::Sequence { index, type_id: segment }
+            }
+            None => Item::Special { token: name.to_owned(), type_id: type_id.unwrap_or(segment) },
+        };
+        items.push(item);
+    }
+    if seen.contains(&false) {
+        return Err(config(format!("{:?} does not place every text", template)));
+    }
+
+    Ok(items)
+}
+
+fn render(items: &[Item]) -> String {
+    let words: Vec<String> = items.iter()
+                                  .map(|item| match item {
+                                      Item::Sequence { index: 0, type_id } => format!("$A:{}", type_id),
+                                      Item::Sequence { type_id, .. } => format!("$B:{}", type_id),
+                                      Item::Special { token, type_id } => format!("{}:{}", token, type_id),
+                                  })
+                                  .collect();
+
+    words.join(" ")
+}
+
+fn config(reason: String) -> Error {
+    Error::Config { reason: format!("invalid template: {}", reason) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_ids_follow_the_segments() {
+        let template = Template::new("<s> $A </s>", "<s> $A </s> </s>:0 $B:3 </s> x:y").unwrap();
+        assert_eq!(template.single(), "<s>:0 $A:0 </s>:0");
+        assert_eq!(template.pair(), "<s>:0 $A:0 </s>:0 </s>:0 $B:3 </s>:3 x:y:3");
+        assert_eq!(Template::new(&template.single(), &template.pair()).unwrap(), template);
+        assert_eq!(template.added_tokens(false), 2);
+        assert_eq!(template.added_tokens(true), 5);
+        assert_eq!(Template::from_json(&template.to_json()).unwrap(), template);
+    }
+
+    #[test]
+    fn every_text_is_placed_once() {
+        for (single, pair) in &[("$A $B", "$A $B"), ("[CLS]", "$A $B"), ("$A $A", "$A $B"), ("$A", "$A"),
+                                ("$A", "$B $A $B"), ("$A", "$A $C")] {
+            assert!(matches!(Template::new(single, pair), Err(Error::Config { .. })), "{} / {}", single, pair);
+        }
+        assert!(Template::new("$A:1", "$B $A").is_ok());
+    }
+}
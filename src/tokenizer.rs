@@ -0,0 +1,553 @@
+use std::any::Any;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use fancy_regex::Regex;
+use serde_json::{json, Value};
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::{Error, Result};
+
+#[cfg(doc)]
+use crate::normalizer::Normalizer;
+
+/// Splits raw text into vocabulary terms
+///
+/// Builders count the terms a tokenizer produces, and a vocabulary
+/// encodes text with the tokenizer it was built with. Closures of type
+/// `Fn(&str) -> Vec<String>` are tokenizers too.
+///
+/// Text reaches the tokenizer after the vocabulary's [`Normalizer`],
+/// which lowercases by default.
+///
+/// Text is streamed to the tokenizer in chunks, each cut just before
+/// ASCII whitespace that follows a character other than whitespace.
+/// Terms may start with whitespace, as [`RegexTokenizer::gpt2`] does,
+/// but should not run from other text into following whitespace.
+///
+/// ```
+/// use tok::{Normalizer, Vocab};
+///
+/// let whitespace = |text: &str| -> Vec<String> {
+///     text.split_whitespace().map(str::to_owned).collect()
+/// };
+/// let vocab = Vocab::builder()
+///     .normalizer(Normalizer::new())
+///     .tokenizer(whitespace)
+///     .build_reader("Hello, world!".as_bytes())?;
+/// assert_eq!(vocab.token_to_id("Hello,"), Some(0));
+/// # Ok::<(), tok::Error>(())
+/// ```
+pub trait Tokenizer: Send + Sync {
+    /// Split `text` into terms
+    fn tokenize(&self, text: &str) -> Vec<String>;
+
+    /// Split `text` into terms with the byte span each was taken from
+    ///
+    /// By default each term is searched for in `text` after the end of
+    /// the previous one. Terms that are not found, because the
+    /// tokenizer rewrote them, get an empty span at that point.
+    ///
+    /// ```
+    /// use tok::{Tokenizer, WhitespaceTokenizer};
+    ///
+    /// assert_eq!(WhitespaceTokenizer.tokenize_with_offsets(" to be"),
+    ///            vec![("to".to_owned(), (1, 3)), ("be".to_owned(), (4, 6))]);
+    /// ```
+    fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, (usize, usize))> {
+        let mut end = 0;
+        self.tokenize(text)
+            .into_iter()
+            .map(|term| {
+                let start = match text[end..].find(term.as_str()) {
+                    Some(at) => end + at,
+                    None => return (term, (end, end)),
+                };
+                end = start + term.len();
+                (term, (start, end))
+            })
+            .collect()
+    }
+}
+
+impl<F> Tokenizer for F
+where
+    F: Fn(&str) -> Vec<String> + Send + Sync,
+{
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self(text)
+    }
+}
+
+/// The tokenizer used unless another is configured
+///
+/// Splits on anything that is not alphanumeric or an apostrophe and
+/// drops empty pieces. Combining marks stay in the word they follow,
+/// so the dot that lowercasing `İ` leaves behind does not split a
+/// word. Case is left to the [`Normalizer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultTokenizer;
+
+impl DefaultTokenizer {
+    /// Get the byte span of every word of `text`
+    fn spans(text: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = None;
+        for (at, c) in text.char_indices() {
+            let inside = c.is_alphanumeric() || c == '\'' || (start.is_some() && is_combining_mark(c));
+            match start {
+                None if inside => start = Some(at),
+                Some(from) if !inside => {
+                    spans.push((from, at));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(from) = start {
+            spans.push((from, text.len()));
+        }
+
+        spans
+    }
+}
+
+impl Tokenizer for DefaultTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        DefaultTokenizer::spans(text).into_iter()
+                                     .map(|(start, end)| text[start..end].to_owned())
+                                     .collect()
+    }
+
+    fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, (usize, usize))> {
+        DefaultTokenizer::spans(text).into_iter()
+                                     .map(|(start, end)| (text[start..end].to_owned(), (start, end)))
+                                     .collect()
+    }
+}
+
+/// Splits on whitespace only, keeping punctuation attached to words
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_owned).collect()
+    }
+}
+
+/// Splits on whitespace and makes every punctuation mark its own term
+///
+/// Anything that is neither alphanumeric nor whitespace counts as
+/// punctuation, so `don't` becomes `don`, `'` and `t`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PunctuationTokenizer;
+
+impl Tokenizer for PunctuationTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        for word in text.split_whitespace() {
+            isolate(word, |c| !c.is_alphanumeric(), &mut terms);
+        }
+
+        terms
+    }
+}
+
+/// Splits on Unicode word boundaries (UAX #29), keeping only words
+///
+/// Apostrophes and periods inside words stay attached, as in `don't`
+/// or `e.g`, and runs of punctuation and whitespace are dropped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WordTokenizer;
+
+impl Tokenizer for WordTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.unicode_words().map(str::to_owned).collect()
+    }
+
+    fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, (usize, usize))> {
+        text.unicode_word_indices()
+            .map(|(at, word)| (word.to_owned(), (at, at + word.len())))
+            .collect()
+    }
+}
+
+/// Pattern used by GPT-2 to split text before byte-pair encoding
+const GPT2_PATTERN: &str =
+    r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+
+/// Makes every match of a regular expression a term
+///
+/// Text between matches is dropped. Patterns may use look-around and
+/// backreferences. If matching backtracks too far, the rest of the text
+/// from the end of the last match becomes a single term.
+///
+/// ```
+/// use tok::{RegexTokenizer, Tokenizer};
+///
+/// let gpt2 = RegexTokenizer::gpt2();
+/// assert_eq!(gpt2.tokenize("It's  2 cats!"), vec!["It", "'s", " ", " 2", " cats", "!"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexTokenizer {
+    regex: Regex,
+}
+
+impl RegexTokenizer {
+    /// Compile a tokenizer from a pattern
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - regular expression matching one term
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| Error::Config {
+            reason: format!("invalid pattern {:?}: {}", pattern, e),
+        })?;
+
+        Ok(RegexTokenizer { regex })
+    }
+
+    /// The GPT-2 pre-tokenizer
+    ///
+    /// Splits off English contractions, and runs of letters, of digits
+    /// and of other symbols, each with at most one leading space.
+    /// Whitespace before a word is kept apart from its last space.
+    pub fn gpt2() -> Self {
+        RegexTokenizer::new(GPT2_PATTERN).expect("the GPT-2 pattern compiles")
+    }
+
+    /// Get the pattern the tokenizer was compiled from
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+}
+
+impl Tokenizer for RegexTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenize_with_offsets(text).into_iter().map(|(term, _)| term).collect()
+    }
+
+    fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, (usize, usize))> {
+        let mut terms = Vec::new();
+        let mut end = 0;
+        for found in self.regex.find_iter(text) {
+            match found {
+                Ok(found) => {
+                    terms.push((found.as_str().to_owned(), (found.start(), found.end())));
+                    end = found.end();
+                }
+                // Matching only fails when backtracking runs away. Rather
+                // than lose the rest of the text, keep it as one term.
+                Err(_) => {
+                    if end < text.len() {
+                        terms.push((text[end..].to_owned(), (end, text.len())));
+                    }
+                    break;
+                }
+            }
+        }
+
+        terms
+    }
+}
+
+/// Splits every term of another tokenizer so each digit stands alone
+///
+/// ```
+/// use tok::{SplitDigits, Tokenizer, WhitespaceTokenizer};
+///
+/// let tokenizer = SplitDigits(WhitespaceTokenizer);
+/// assert_eq!(tokenizer.tokenize("route 66a"), vec!["route", "6", "6", "a"]);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SplitDigits<T>(pub T);
+
+impl<T: Tokenizer> Tokenizer for SplitDigits<T> {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut terms = Vec::new();
+        for term in self.0.tokenize(text) {
+            if term.chars().any(char::is_numeric) {
+                isolate(&term, char::is_numeric, &mut terms);
+            } else {
+                terms.push(term);
+            }
+        }
+
+        terms
+    }
+
+    fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, (usize, usize))> {
+        let mut terms = Vec::new();
+        for (term, (start, end)) in self.0.tokenize_with_offsets(text) {
+            // Rewritten terms have no span to split.
+            if term.chars().any(char::is_numeric) && text[start..end] == term {
+                for (from, to) in pieces(&term, char::is_numeric) {
+                    terms.push((term[from..to].to_owned(), (start + from, start + to)));
+                }
+            } else {
+                terms.push((term, (start, end)));
+            }
+        }
+
+        terms
+    }
+}
+
+/// Push the pieces of `word`, with each character matching `alone` on its own
+fn isolate<F: Fn(char) -> bool>(word: &str, alone: F, terms: &mut Vec<String>) {
+    terms.extend(pieces(word, alone).map(|(start, end)| word[start..end].to_owned()));
+}
+
+/// Get the byte spans of the pieces of `word`, with each character matching `alone` on its own
+fn pieces<F: Fn(char) -> bool>(word: &str, alone: F) -> impl Iterator<Item = (usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for (idx, c) in word.char_indices().filter(|&(_, c)| alone(c)) {
+        if idx > start {
+            spans.push((start, idx));
+        }
+        start = idx + c.len_utf8();
+        spans.push((idx, start));
+    }
+    if start < word.len() {
+        spans.push((start, word.len()));
+    }
+
+    spans.into_iter()
+}
+
+/// A tokenizer shared between a builder and the vocabularies it builds
+///
+/// Built-in tokenizers keep a description that is saved with the
+/// vocabulary; closures and other custom tokenizers have none.
+#[derive(Clone)]
+pub(crate) struct SharedTokenizer {
+    tokenizer: Arc<dyn Tokenizer>,
+    saved: Option<Value>,
+}
+
+impl SharedTokenizer {
+    pub(crate) fn new<T: Tokenizer + 'static>(tokenizer: T) -> Self {
+        let saved = describe(&tokenizer);
+        SharedTokenizer { tokenizer: Arc::new(tokenizer), saved }
+    }
+
+    /// Get the description to save, if the tokenizer is a built-in one
+    pub(crate) fn to_json(&self) -> Option<&Value> {
+        self.saved.as_ref()
+    }
+
+    /// Check whether this is the [`DefaultTokenizer`]
+    pub(crate) fn is_default(&self) -> bool {
+        self.saved.as_ref() == Some(&json!({ "type": "default" }))
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<SharedTokenizer> {
+        let config = |reason: &str| Error::Config { reason: format!("invalid tokenizer: {}", reason) };
+        let tokenizer = match value.get("type").and_then(Value::as_str) {
+            Some("default") => SharedTokenizer::new(DefaultTokenizer),
+            Some("whitespace") => SharedTokenizer::new(WhitespaceTokenizer),
+            Some("punctuation") => SharedTokenizer::new(PunctuationTokenizer),
+            Some("words") => SharedTokenizer::new(WordTokenizer),
+            Some("gpt2") => SharedTokenizer::new(RegexTokenizer::gpt2()),
+            Some("regex") => {
+                let pattern = value.get("pattern")
+                                   .and_then(Value::as_str)
+                                   .ok_or_else(|| config("regex pattern is not a string"))?;
+                SharedTokenizer::new(RegexTokenizer::new(pattern)?)
+            }
+            Some("split_digits") => {
+                let inner = value.get("tokenizer").ok_or_else(|| config("split_digits has no tokenizer"))?;
+                SharedTokenizer::new(SplitDigits(SharedTokenizer::from_json(inner)?))
+            }
+            _ => return Err(config("unknown type")),
+        };
+
+        Ok(tokenizer)
+    }
+}
+
+/// Describe a built-in tokenizer, or return `None` for any other
+fn describe(tokenizer: &dyn Any) -> Option<Value> {
+    let named = |name: &str| Some(json!({ "type": name }));
+    let split = |inner: Option<Value>| inner.map(|inner| json!({ "type": "split_digits", "tokenizer": inner }));
+    if let Some(shared) = tokenizer.downcast_ref::<SharedTokenizer>() {
+        shared.saved.clone()
+    } else if tokenizer.is::<DefaultTokenizer>() {
+        named("default")
+    } else if tokenizer.is::<WhitespaceTokenizer>() {
+        named("whitespace")
+    } else if tokenizer.is::<PunctuationTokenizer>() {
+        named("punctuation")
+    } else if tokenizer.is::<WordTokenizer>() {
+        named("words")
+    } else if let Some(regex) = tokenizer.downcast_ref::<RegexTokenizer>() {
+        match regex.pattern() {
+            GPT2_PATTERN => named("gpt2"),
+            pattern => Some(json!({ "type": "regex", "pattern": pattern })),
+        }
+    } else if let Some(SplitDigits(inner)) = tokenizer.downcast_ref::<SplitDigits<SharedTokenizer>>() {
+        split(describe(inner))
+    } else if let Some(SplitDigits(inner)) = tokenizer.downcast_ref::<SplitDigits<DefaultTokenizer>>() {
+        split(describe(inner))
+    } else if let Some(SplitDigits(inner)) = tokenizer.downcast_ref::<SplitDigits<WhitespaceTokenizer>>() {
+        split(describe(inner))
+    } else if let Some(SplitDigits(inner)) = tokenizer.downcast_ref::<SplitDigits<PunctuationTokenizer>>() {
+        split(describe(inner))
+    } else if let Some(SplitDigits(inner)) = tokenizer.downcast_ref::<SplitDigits<WordTokenizer>>() {
+        split(describe(inner))
+    } else if let Some(SplitDigits(inner)) = tokenizer.downcast_ref::<SplitDigits<RegexTokenizer>>() {
+        split(describe(inner))
+    } else {
+        None
+    }
+}
+
+impl Default for SharedTokenizer {
+    fn default() -> Self {
+        SharedTokenizer::new(DefaultTokenizer)
+    }
+}
+
+impl Tokenizer for SharedTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(text)
+    }
+
+    fn tokenize_with_offsets(&self, text: &str) -> Vec<(String, (usize, usize))> {
+        self.tokenizer.tokenize_with_offsets(text)
+    }
+}
+
+impl Deref for SharedTokenizer {
+    type Target = dyn Tokenizer;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.tokenizer
+    }
+}
+
+impl fmt::Debug for SharedTokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tokenizer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keeps_apostrophes_and_case() {
+        assert_eq!(DefaultTokenizer.tokenize("Don't STOP-me, now!"),
+                   vec!["Don't", "STOP", "me", "now"]);
+        assert!(DefaultTokenizer.tokenize(" ... ").is_empty());
+    }
+
+    #[test]
+    fn default_keeps_combining_marks_in_words() {
+        assert_eq!(DefaultTokenizer.tokenize("i\u{307}stanbul cafe\u{301}, \u{301}x"),
+                   vec!["i\u{307}stanbul", "cafe\u{301}", "x"]);
+        assert_eq!(DefaultTokenizer.tokenize_with_offsets("to i\u{307}"),
+                   vec![("to".to_owned(), (0, 2)), ("i\u{307}".to_owned(), (3, 6))]);
+    }
+
+    const SAMPLE: &str = "Don't say 'maybe' -- it's 2024, e.g. now!";
+
+    fn default_terms() -> Vec<String> {
+        DefaultTokenizer.tokenize(SAMPLE)
+    }
+
+    #[test]
+    fn whitespace_keeps_punctuation_the_default_drops() {
+        assert_eq!(default_terms(), vec!["Don't", "say", "'maybe'", "it's", "2024", "e", "g", "now"]);
+        assert_eq!(WhitespaceTokenizer.tokenize(SAMPLE),
+                   vec!["Don't", "say", "'maybe'", "--", "it's", "2024,", "e.g.", "now!"]);
+    }
+
+    #[test]
+    fn punctuation_becomes_terms_instead_of_vanishing() {
+        let terms = PunctuationTokenizer.tokenize(SAMPLE);
+        assert_eq!(terms, vec!["Don", "'", "t", "say", "'", "maybe", "'", "-", "-", "it", "'", "s",
+                               "2024", ",", "e", ".", "g", ".", "now", "!"]);
+        let words: Vec<&String> = terms.iter().filter(|t| t.chars().all(char::is_alphanumeric)).collect();
+        assert!(words.len() > default_terms().len());
+    }
+
+    #[test]
+    fn words_follow_unicode_boundaries() {
+        assert_eq!(WordTokenizer.tokenize(SAMPLE),
+                   vec!["Don't", "say", "maybe", "it's", "2024", "e.g", "now"]);
+        assert_eq!(WordTokenizer.tokenize("l'été 3.5 ½"), vec!["l'été", "3.5", "½"]);
+        assert_eq!(DefaultTokenizer.tokenize("l'été 3.5 ½"), vec!["l'été", "3", "5", "½"]);
+    }
+
+    #[test]
+    fn gpt2_keeps_every_character() {
+        let terms = RegexTokenizer::gpt2().tokenize(SAMPLE);
+        assert_eq!(terms, vec!["Don", "'t", " say", " '", "maybe", "'", " --", " it", "'s", " 2024",
+                               ",", " e", ".", "g", ".", " now", "!"]);
+        assert_eq!(terms.concat(), SAMPLE);
+        assert_eq!(RegexTokenizer::gpt2().tokenize("a \n\n  b "), vec!["a", " \n\n ", " b", " "]);
+    }
+
+    #[test]
+    fn custom_patterns_compile_or_fail() {
+        let hashtags = RegexTokenizer::new(r"#\w+").unwrap();
+        assert_eq!(hashtags.tokenize("#rust is #fun"), vec!["#rust", "#fun"]);
+        assert_eq!(hashtags.pattern(), r"#\w+");
+        assert!(matches!(RegexTokenizer::new("(unclosed"), Err(Error::Config { .. })));
+    }
+
+    #[test]
+    fn runaway_matches_keep_the_rest_of_the_text() {
+        let regex = fancy_regex::RegexBuilder::new(r"(a|aa)+(?=b)|\w+").backtrack_limit(1000).build().unwrap();
+        let tokenizer = RegexTokenizer { regex };
+        let text = format!("x {} y", "a".repeat(30));
+        assert_eq!(tokenizer.tokenize(&text), vec!["x", &text[1..]]);
+        assert_eq!(tokenizer.tokenize_with_offsets(&text),
+                   vec![("x".to_owned(), (0, 1)), (text[1..].to_owned(), (1, text.len()))]);
+    }
+
+    #[test]
+    fn split_digits_wraps_any_tokenizer() {
+        assert_eq!(SplitDigits(DefaultTokenizer).tokenize(SAMPLE),
+                   vec!["Don't", "say", "'maybe'", "it's", "2", "0", "2", "4", "e", "g", "now"]);
+        assert_eq!(SplitDigits(RegexTokenizer::gpt2()).tokenize("in 1984"), vec!["in", " ", "1", "9", "8", "4"]);
+    }
+
+    #[test]
+    fn offsets_locate_every_term() {
+        let tokenizers: Vec<SharedTokenizer> = vec![
+            SharedTokenizer::new(DefaultTokenizer),
+            SharedTokenizer::new(PunctuationTokenizer),
+            SharedTokenizer::new(WordTokenizer),
+            SharedTokenizer::new(RegexTokenizer::gpt2()),
+            SharedTokenizer::new(SplitDigits(WhitespaceTokenizer)),
+        ];
+        for tokenizer in &tokenizers {
+            let found = tokenizer.tokenize_with_offsets(SAMPLE);
+            let terms: Vec<String> = found.iter().map(|(term, _)| term.clone()).collect();
+            assert_eq!(terms, tokenizer.tokenize(SAMPLE));
+            for (term, (start, end)) in found {
+                assert_eq!(&SAMPLE[start..end], term);
+            }
+        }
+
+        let regex = RegexTokenizer::new(r"\bb\b").unwrap();
+        assert_eq!(regex.tokenize_with_offsets("ab b"), vec![("b".to_owned(), (3, 4))]);
+        let upper = |text: &str| -> Vec<String> { text.split(' ').map(str::to_uppercase).collect() };
+        assert_eq!(upper.tokenize_with_offsets("a b").last(), Some(&("B".to_owned(), (0, 0))));
+    }
+
+    #[test]
+    fn closures_are_tokenizers() {
+        let chars = SharedTokenizer::new(|text: &str| -> Vec<String> {
+            text.chars().map(String::from).collect()
+        });
+        assert_eq!(chars.tokenize("ab"), vec!["a", "b"]);
+    }
+}
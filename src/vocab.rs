@@ -1,133 +1,1114 @@
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 use std::collections::HashMap;
 
-use pyo3::prelude::*;
+use crate::builder::VocabBuilder;
+use rayon::prelude::*;
+
+use crate::encoding::{self, Encoder, Encoding, Padding, Truncation};
+use crate::error::{Error, Result};
+use crate::format::{bert, binary, json, tsv};
+use crate::model::Model;
+use crate::normalizer::Normalizer;
+use crate::template::Template;
+use crate::tokenizer::{DefaultTokenizer, SharedTokenizer, Tokenizer};
+#[cfg(doc)]
+use crate::tokenizer::SplitDigits;
+#[cfg(doc)]
+use crate::mapped::MappedVocab;
+
+/// Padding token
+pub const PAD: &str = "<pad>";
+/// Unknown token, used for out-of-vocabulary terms
+pub const UNK: &str = "<unk>";
+/// Beginning of sequence token
+pub const BOS: &str = "<bos>";
+/// End of sequence token
+pub const EOS: &str = "<eos>";
+/// Mask token
+pub const MASK: &str = "<mask>";
 
 /// Vocabulary for NLP applications
 ///
-/// This is a mapping from tokenized 
+/// This is a mapping from tokenized
 /// vocabulary terms to integer tokens.
-#[pyclass]
+///
+/// Two vocabularies are equal when they assign the same ids to the
+/// same terms, reserve the same special tokens, normalize text the
+/// same way, share a [`Model`] and place texts in the same [`Template`].
+/// Term frequencies are statistics about the corpus and are not
+/// compared, and neither are the tokenizer, padding and truncation.
+#[derive(Debug, Clone, Default)]
 pub struct Vocab {
     /// Mapping from tokens to integers
     map: HashMap<String, i32>,
+    /// Reverse mapping from integers back to tokens
+    rev: HashMap<i32, String>,
+    /// Number of times each term was seen while building
+    counts: HashMap<String, u64>,
+    /// Reserved special tokens, in id order
+    specials: Vec<String>,
+    /// Id used for out-of-vocabulary terms
+    unk: Option<i32>,
+    /// Applied to text passed to [`Vocab::encode`] before splitting
+    normalizer: Normalizer,
+    /// Splits text passed to [`Vocab::encode`]
+    tokenizer: SharedTokenizer,
+    /// Splits terms into vocabulary entries
+    model: Model,
+    /// Places special tokens around encoded texts
+    template: Option<Template>,
+    /// Applied by [`Vocab::encode_batch`] and [`Vocab::encode_pair_batch`]
+    padding: Option<Padding>,
+    /// Applied to every [`Encoding`]
+    truncation: Option<Truncation>,
+}
+
+impl PartialEq for Vocab {
+    fn eq(&self, other: &Vocab) -> bool {
+        self.map == other.map
+            && self.specials == other.specials
+            && self.unk == other.unk
+            && self.normalizer == other.normalizer
+            && self.model == other.model
+            && self.template == other.template
+    }
 }
 
-#[pymethods]
 impl Vocab {
-    /// Create a Vocabulary
+    /// Build a vocabulary and its reverse index from a term mapping
     ///
-    /// # Arguments
+    /// Every entry of `specials`, and `unk` if given, must be in `map`.
+    pub(crate) fn from_parts(map: HashMap<String, i32>,
+                             counts: HashMap<String, u64>,
+                             mut specials: Vec<String>,
+                             unk: Option<&str>) -> Self {
+        let rev = map.iter()
+                     .map(|(voc, &tok)| (tok, voc.to_owned()))
+                     .collect();
+        specials.sort_by_key(|term| map[term]);
+        let unk = unk.map(|term| map[term]);
+
+        Vocab {
+            map,
+            rev,
+            counts,
+            specials,
+            unk,
+            normalizer: Normalizer::default(),
+            tokenizer: SharedTokenizer::default(),
+            model: Model::Word,
+            template: None,
+            padding: None,
+            truncation: None,
+        }
+    }
+
+    /// Build a vocabulary sharing the special tokens and pipeline of `self`
     ///
-    /// * `path` - Path to a raw text file to be parsedA
-    #[new]
-    pub fn new(fpath: &str) -> PyResult<Self> {
+    /// Special tokens keep their order and take the lowest ids, followed
+    /// by `terms` in order.
+    pub(crate) fn derive(&self, terms: Vec<String>, counts: HashMap<String, u64>, model: Model) -> Vocab {
         let mut map = HashMap::new();
-        let contents = Vocab::read_file(fpath);
-        let tokens = Vocab::tokenize(contents);
-
-        let mut tok = 0;
-        for term in &tokens {
-            if !map.contains_key(term) {
-                map.insert(term.to_owned(), tok);
-                tok += 1;
-            }
+        for term in self.specials.iter().cloned().chain(terms) {
+            let id = map.len() as i32;
+            map.entry(term).or_insert(id);
         }
+        let unk = self.unk.and_then(|id| self.id_to_token(id));
+        let mut vocab = Vocab::from_parts(map, counts, self.specials.clone(), unk);
+        vocab.normalizer = self.normalizer.clone();
+        vocab.tokenizer = self.tokenizer.clone();
+        vocab.model = model;
+        vocab.template = self.template.clone();
+        vocab.padding = self.padding.clone();
+        vocab.truncation = self.truncation;
+
+        vocab
+    }
+
+    /// Replace the normalizer applied before tokenizing
+    ///
+    /// The normalizer is saved by [`Vocab::write`] and
+    /// [`Vocab::write_binary`], so it only needs setting on vocabularies
+    /// read from JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `normalizer` - steps applied to raw text
+    pub fn with_normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// Get the normalizer applied before tokenizing
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
+    /// Replace the tokenizer used by [`Vocab::encode`]
+    ///
+    /// Built-in tokenizers, with their pattern and [`SplitDigits`]
+    /// wrapping, are saved by [`Vocab::write`] and
+    /// [`Vocab::write_binary`]. Closures and other custom tokenizers
+    /// cannot be saved: vocabularies loaded from disk then use
+    /// [`DefaultTokenizer`], so set the tokenizer again before encoding
+    /// text.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokenizer` - splits raw text into terms
+    pub fn with_tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = SharedTokenizer::new(tokenizer);
+        self
+    }
+
+    /// Share an already wrapped tokenizer
+    pub(crate) fn set_tokenizer(&mut self, tokenizer: SharedTokenizer) {
+        self.tokenizer = tokenizer;
+    }
+
+    /// Get the wrapped tokenizer, to save or share it
+    pub(crate) fn shared_tokenizer(&self) -> &SharedTokenizer {
+        &self.tokenizer
+    }
+
+    /// Replace the model splitting terms into entries
+    ///
+    /// Use this to pair entries read from another tool, such as a
+    /// GPT-2 `vocab.json`, with the merges learned alongside them.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - splits each term into vocabulary entries
+    pub fn with_model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Get the model splitting terms into entries
+    ///
+    /// Vocabularies built from a corpus are word-level; subword models
+    /// come from a trainer such as [`BpeTrainer`](crate::BpeTrainer).
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// Place special tokens around every [`Encoding`]
+    ///
+    /// The template is saved by [`Vocab::write`] and
+    /// [`Vocab::write_binary`]. Fails if an entry the template inserts is
+    /// not in the vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - where special tokens go, such as [`Template::bert`]
+    pub fn with_template(mut self, template: Template) -> Result<Self> {
+        template.check(|token| self.map.contains_key(token))?;
+        self.template = Some(template);
+        Ok(self)
+    }
 
-        Ok(Vocab {map})
+    /// Get the template placing special tokens, if there is one
+    pub fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+
+    /// Stop placing special tokens around encodings
+    pub fn without_template(mut self) -> Self {
+        self.template = None;
+        self
+    }
+
+    /// Pad the encodings of each batch to one length
+    ///
+    /// Padding is not saved with the vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `padding` - length, side and token to pad with
+    pub fn with_padding(mut self, padding: Padding) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Get how batches are padded, if they are
+    pub fn padding(&self) -> Option<&Padding> {
+        self.padding.as_ref()
+    }
+
+    /// Stop padding batches
+    pub fn without_padding(mut self) -> Self {
+        self.padding = None;
+        self
+    }
+
+    /// Cut encodings down to a maximum length
+    ///
+    /// Truncation applies to [`Encoding`]s, not to the ids returned by
+    /// [`Vocab::encode`], and is not saved with the vocabulary.
+    ///
+    /// # Arguments
+    ///
+    /// * `truncation` - maximum length and how to cut
+    pub fn with_truncation(mut self, truncation: Truncation) -> Self {
+        self.truncation = Some(truncation);
+        self
+    }
+
+    /// Get how encodings are truncated, if they are
+    pub fn truncation(&self) -> Option<&Truncation> {
+        self.truncation.as_ref()
+    }
+
+    /// Stop truncating encodings
+    pub fn without_truncation(mut self) -> Self {
+        self.truncation = None;
+        self
+    }
+
+    /// Start configuring a vocabulary
+    ///
+    /// See [`VocabBuilder`] for the available options.
+    pub fn builder() -> VocabBuilder {
+        VocabBuilder::new()
+    }
+
+    /// Create a Vocabulary
+    ///
+    /// Terms are assigned ids in the order they are first seen,
+    /// starting at 0. Use [`Vocab::builder`] to reserve special tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a raw text file to be parsed
+    pub fn new(fpath: &str) -> Result<Self> {
+        VocabBuilder::new().build(fpath)
+    }
+
+    /// Create a Vocabulary from any reader, such as stdin
+    ///
+    /// See [`VocabBuilder::build_reader`].
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        VocabBuilder::new().build_reader(reader)
+    }
+
+    /// Create a Vocabulary from several raw text files
+    ///
+    /// See [`VocabBuilder::build_files`].
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        VocabBuilder::new().build_files(paths)
+    }
+
+    /// Create a Vocabulary from every file in a directory
+    ///
+    /// See [`VocabBuilder::build_dir`].
+    pub fn from_dir<P: AsRef<Path>>(dir: P, recursive: bool) -> Result<Self> {
+        VocabBuilder::new().build_dir(dir, recursive)
+    }
+
+    /// Create a Vocabulary from every file matching a glob pattern
+    ///
+    /// See [`VocabBuilder::build_glob`].
+    pub fn from_glob(pattern: &str) -> Result<Self> {
+        VocabBuilder::new().build_glob(pattern)
     }
 
     /// Read in a file
-    #[staticmethod]
-    pub fn read_file(fpath: &str) -> String {
-        let mut file = File::open(fpath).expect("Cannot open file!");
+    pub fn read_file(fpath: &str) -> Result<String> {
+        let mut file = File::open(fpath).map_err(|e| Error::io(fpath, e))?;
         let mut contents = String::new();
-        file.read_to_string(&mut contents).expect("Cannot read file!");
+        file.read_to_string(&mut contents).map_err(|e| Error::io(fpath, e))?;
 
-        contents
-    } 
+        Ok(contents)
+    }
 
     /// Tokenize raw text
     ///
-    /// Strip whitespace, lowercase terms, and remove punctuation. 
-    /// We then return a vector of token Strings.
+    /// Strip whitespace, lowercase terms, and remove punctuation.
+    /// We then return a vector of token Strings. This is what the
+    /// default [`Normalizer`] and [`DefaultTokenizer`] do together.
+    /// See [`Vocab::encode_with_offsets`] to keep where each term came
+    /// from.
     ///
     /// # Arguments
-    /// 
+    ///
     /// * `text` - raw text String from which a vocabulary is built
-    #[staticmethod]
     pub fn tokenize(text: String) -> Vec<String> {
-        let tokens: Vec<String> = text.split(|c: char| !(c.is_alphanumeric() || c == '\''))
-                                      .filter(|s| !s.is_empty())
-                                      .map(|s| s.to_string())
-                                      .map(|s| s.to_lowercase())
-                                      .collect();
-
-        tokens
+        DefaultTokenizer.tokenize(&Normalizer::default().normalize(&text))
     }
 
     /// Load a previously built vocabulary from disk
     ///
+    /// Reads files written by [`Vocab::write`], as well as the older
+    /// headerless format of one `term<TAB>token` pair per line, JSON
+    /// files written by [`Vocab::write_json`] and binary files written
+    /// by [`Vocab::write_binary`]. Binary files are recognised by their
+    /// leading magic bytes. A `.json` or `.tsv` extension picks the text
+    /// format; otherwise a file that parses as a JSON object is read as
+    /// JSON.
+    ///
     /// # Arguments
-    /// 
+    ///
     /// * `path` - Path to a saved vocabulary
-    #[staticmethod]
-    pub fn load(fpath: &str) -> Result<Vocab, std::io::Error> {
-        let mut map = HashMap::new(); 
-        let contents = Vocab::read_file(fpath);
-
-        for line in contents.lines() {
-            let mut chunks = line.splitn(2, '\t');
-            let voc = chunks.next().expect("No vocab term!");
-            let tok = chunks.next().expect("No token!").parse().unwrap();
-            map.insert(voc.to_owned(), tok);
+    pub fn load(fpath: &str) -> Result<Vocab> {
+        let bytes = std::fs::read(fpath).map_err(|e| Error::io(fpath, e))?;
+        if binary::sniff(&bytes) {
+            return binary::parse(&bytes);
+        }
+        let contents = String::from_utf8(bytes).map_err(|e| {
+            Error::io(fpath, std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        })?;
+        let extension = Path::new(fpath).extension().and_then(|ext| ext.to_str());
+
+        match extension {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => json::parse(&contents),
+            Some(ext) if ext.eq_ignore_ascii_case("tsv") => tsv::parse(&contents),
+            _ if json::sniff(&contents) => json::parse(&contents),
+            _ => tsv::parse(&contents),
         }
+    }
+
+    /// Load a vocabulary from a JSON file
+    ///
+    /// See [`Vocab::from_json`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a `vocab.json` file
+    pub fn load_json(fpath: &str) -> Result<Vocab> {
+        let contents = Vocab::read_file(fpath)?;
+
+        json::parse(&contents)
+    }
+
+    /// Parse a vocabulary from a JSON object of terms to ids
+    ///
+    /// This is the `vocab.json` layout used by Hugging Face tokenizers.
+    /// Special tokens and frequencies are not part of the format.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - JSON text such as `{"the": 0, "cat": 1}`
+    pub fn from_json(json: &str) -> Result<Vocab> {
+        json::parse(json)
+    }
+
+    /// Render the vocabulary as a JSON object of terms to ids
+    ///
+    /// Entries are sorted by id. Special token flags and frequencies
+    /// are not written.
+    pub fn to_json(&self) -> String {
+        json::render(self)
+    }
+
+    /// Load a vocabulary from a BERT `vocab.txt` file
+    ///
+    /// See [`Vocab::from_bert`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a `vocab.txt` file
+    pub fn load_bert(fpath: &str) -> Result<Vocab> {
+        let contents = Vocab::read_file(fpath)?;
+
+        bert::parse(&contents)
+    }
+
+    /// Parse a vocabulary from one term per line, as in BERT's `vocab.txt`
+    ///
+    /// Ids follow line order. `[PAD]`, `[UNK]`, `[CLS]`, `[SEP]` and
+    /// `[MASK]` become special tokens, with `[UNK]` as the unknown
+    /// token, and terms are split with [`WordPiece`](crate::WordPiece).
+    /// BERT splits punctuation from words, so pair with
+    /// [`PunctuationTokenizer`](crate::PunctuationTokenizer).
+    ///
+    /// # Arguments
+    ///
+    /// * `contents` - one term per line
+    pub fn from_bert(contents: &str) -> Result<Vocab> {
+        bert::parse(contents)
+    }
+
+    /// Write the vocabulary to disk as a BERT `vocab.txt`
+    ///
+    /// Terms are written one per line in id order, so ids must run from
+    /// 0 without gaps to read back the same. Only the terms are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path to save the `vocab.txt` file
+    pub fn write_bert(&self, fpath: &str) -> Result<()> {
+        std::fs::write(fpath, bert::render(self)).map_err(|e| Error::io(fpath, e))
+    }
+
+    /// Load a binary vocabulary into memory
+    ///
+    /// Use [`MappedVocab::open`] to query the file in place instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to a file written by [`Vocab::write_binary`]
+    pub fn load_binary(fpath: &str) -> Result<Vocab> {
+        let bytes = std::fs::read(fpath).map_err(|e| Error::io(fpath, e))?;
+
+        binary::parse(&bytes)
+    }
+
+    /// Parse a vocabulary from the binary format
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - contents produced by [`Vocab::to_binary`]
+    pub fn from_binary(bytes: &[u8]) -> Result<Vocab> {
+        binary::parse(bytes)
+    }
+
+    /// Render the vocabulary in the binary format
+    ///
+    /// The format keeps special tokens but not frequencies. See
+    /// [`MappedVocab`] for querying it without loading.
+    pub fn to_binary(&self) -> Vec<u8> {
+        binary::render(self)
+    }
+
+    /// Write the vocabulary to disk in the binary format
+    ///
+    /// See [`Vocab::to_binary`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path to save the binary vocabulary
+    pub fn write_binary(&self, fpath: &str) -> Result<()> {
+        std::fs::write(fpath, self.to_binary()).map_err(|e| Error::io(fpath, e))
+    }
 
-        Ok(Vocab {map})
-    } 
+    /// Write the vocabulary to disk as JSON
+    ///
+    /// See [`Vocab::to_json`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path to save the `vocab.json` file
+    pub fn write_json(&self, fpath: &str) -> Result<()> {
+        std::fs::write(fpath, self.to_json()).map_err(|e| Error::io(fpath, e))
+    }
 
     /// Write the vocabulary to disk
-    /// 
-    /// Saved as a `.tsv` file, where each line is in the following format:
     ///
-    /// ```
-    /// term    token 
-    /// term    token
+    /// Saved as a versioned `.tsv` file with a header line, followed by
+    /// one line per term, sorted by token. With `→` marking a tab:
+    ///
+    /// ```text
+    /// #tok-vocab 2→term→id→flag→freq
+    /// #normalizer→["lowercase"]
+    /// #model→{"type":"word"}
+    /// <unk>→0→unk→0
+    /// term→1→→42
     /// ...
     /// ```
     ///
-    /// # Arguments 
-    /// 
-    /// * `path` - path to save the vocabulary tsv file 
-    pub fn write(&self, fpath: &str) -> std::io::Result<()> {
-        let mut contents = String::new();
-        for (voc, tok) in &self.map {
-            contents.push_str(voc);
-            contents.push('\t');
-            contents.push_str(&tok.to_string());
-            contents.push('\n');
-        }
+    /// The flag marks special tokens and the unknown token, and the
+    /// frequency column is only written when counts are known. Tabs,
+    /// newlines and other control characters in terms are escaped.
+    /// The normalizer, [`Model`], built-in tokenizer and any
+    /// [`Template`] are saved on `#` lines after the header. Custom
+    /// tokenizers are not saved, see [`Vocab::with_tokenizer`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path to save the vocabulary tsv file
+    pub fn write(&self, fpath: &str) -> Result<()> {
+        let contents = tsv::render(self);
 
-        std::fs::write(fpath, contents)
+        std::fs::write(fpath, contents).map_err(|e| Error::io(fpath, e))
     }
 
     /// Get the number of vocabulary terms
     pub fn size(&self) -> usize {
         self.map.len()
     }
+
+    /// Get every term with its token, sorted by token
+    pub fn entries(&self) -> Vec<(&str, i32)> {
+        let mut entries: Vec<(&str, i32)> = self.map.iter()
+                                                .map(|(voc, &tok)| (voc.as_str(), tok))
+                                                .collect();
+        entries.sort_by_key(|&(_, tok)| tok);
+
+        entries
+    }
+
+    /// Get the number of times a term was seen while building
+    ///
+    /// Returns 0 for terms outside the vocabulary, and for every term
+    /// of a vocabulary loaded from a file without frequencies.
+    pub fn frequency(&self, term: &str) -> u64 {
+        self.counts.get(term).copied().unwrap_or(0)
+    }
+
+    /// Get the `n` most frequent terms with their counts
+    ///
+    /// Terms are sorted by descending count, and terms with equal
+    /// counts are sorted alphabetically.
+    pub fn most_common(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut common: Vec<(&str, u64)> = self.counts.iter()
+                                               .map(|(voc, &count)| (voc.as_str(), count))
+                                               .collect();
+        common.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        common.truncate(n);
+
+        common
+    }
+
+    /// Whether term frequencies are known for this vocabulary
+    pub(crate) fn has_frequencies(&self) -> bool {
+        !self.counts.is_empty()
+    }
+
+    /// Get the frequency of every term seen while building
+    pub(crate) fn counts(&self) -> &HashMap<String, u64> {
+        &self.counts
+    }
+
+    /// Get the reserved special tokens, in id order
+    pub fn special_tokens(&self) -> &[String] {
+        &self.specials
+    }
+
+    /// Check whether a term is a reserved special token
+    pub fn is_special(&self, term: &str) -> bool {
+        self.specials.iter().any(|s| s == term)
+    }
+
+    /// Get the id used for out-of-vocabulary terms, if any
+    pub fn unk_id(&self) -> Option<i32> {
+        self.unk
+    }
+
+    /// Look up the token id of a vocabulary term
+    pub fn token_to_id(&self, term: &str) -> Option<i32> {
+        self.map.get(term).copied()
+    }
+
+    /// Look up the vocabulary term for a token id
+    pub fn id_to_token(&self, id: i32) -> Option<&str> {
+        self.rev.get(&id).map(String::as_str)
+    }
+
+    /// Encode raw text as token ids
+    ///
+    /// The text is normalized and then split with the vocabulary's
+    /// tokenizer, which is [`DefaultTokenizer`] unless the builder or
+    /// [`Vocab::with_tokenizer`] set another. Terms that are not in the
+    /// vocabulary map to the unknown token, or are skipped if the
+    /// vocabulary has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to encode
+    pub fn encode(&self, text: &str) -> Vec<i32> {
+        self.encode_tokens(&self.terms(text))
+    }
+
+    /// Encode raw text in the `n` most probable ways, best first
+    ///
+    /// Only [`Unigram`](crate::Unigram) vocabularies have more than one
+    /// way to split text; others give the single [`Vocab::encode`]
+    /// result.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to encode
+    /// * `n` - number of encodings to return at most
+    pub fn encode_nbest(&self, text: &str, n: usize) -> Vec<Vec<i32>> {
+        self.model.encode_nbest(&self.terms(text), n, |piece| self.token_to_id(piece), self.unk)
+    }
+
+    /// Encode raw text with a randomly drawn split of each term
+    ///
+    /// Drawing a different split each time a sentence is seen in
+    /// training, known as subword regularization, makes models robust
+    /// to segmentation. Only [`Unigram`](crate::Unigram) vocabularies
+    /// draw splits, see [`Unigram::sample`](crate::Unigram::sample);
+    /// others give the [`Vocab::encode`] result.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to encode
+    /// * `alpha` - smoothing of the split probabilities, such as 0.1
+    /// * `seed` - seeds the random draws
+    pub fn encode_sample(&self, text: &str, alpha: f64, seed: u64) -> Vec<i32> {
+        self.model.encode_sample(&self.terms(text), alpha, seed, |piece| self.token_to_id(piece), self.unk)
+    }
+
+    /// Encode raw text, keeping the span of text behind every token
+    ///
+    /// Ids are found as in [`Vocab::encode`], and each comes with the
+    /// byte and character span of `text` it was made from, and the
+    /// index of the tokenizer term it was split from. See [`Encoding`].
+    /// The encoding is truncated if [`Vocab::with_truncation`] is set,
+    /// leaving room for the tokens of [`Vocab::with_template`]. Fails if
+    /// those tokens alone take up the maximum length.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - raw text to encode
+    pub fn encode_with_offsets(&self, text: &str) -> Result<Encoding> {
+        encoding::encode(self, text, None)
+    }
+
+    /// Encode a pair of texts, such as a question and its context
+    ///
+    /// Both texts are encoded as by [`Vocab::encode_with_offsets`], cut
+    /// together by the [`TruncationStrategy`](crate::TruncationStrategy),
+    /// and joined by the pair template. Without a template the second
+    /// text follows the first, in segment 1. Fails as
+    /// [`Vocab::encode_with_offsets`] does, or if the strategy would have
+    /// to cut one of the texts entirely.
+    ///
+    /// # Arguments
+    ///
+    /// * `first` - raw text of the first sequence
+    /// * `second` - raw text of the second sequence
+    pub fn encode_pair(&self, first: &str, second: &str) -> Result<Encoding> {
+        encoding::encode(self, first, Some(second))
+    }
+
+    /// Encode many texts in parallel
+    ///
+    /// Each text is encoded as by [`Vocab::encode_with_offsets`], and
+    /// the encodings are then padded if [`Vocab::with_padding`] is set.
+    /// Fails if the padding token is not in the vocabulary, or if a text
+    /// fails to encode.
+    ///
+    /// # Arguments
+    ///
+    /// * `texts` - raw texts to encode
+    pub fn encode_batch<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Result<Vec<Encoding>> {
+        self.pad(texts.par_iter().map(|text| self.encode_with_offsets(text.as_ref())))
+    }
+
+    /// Encode many pairs of texts in parallel
+    ///
+    /// Each pair is encoded as by [`Vocab::encode_pair`], then padded as
+    /// by [`Vocab::encode_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - raw texts of the first and second sequences
+    pub fn encode_pair_batch<A, B>(&self, pairs: &[(A, B)]) -> Result<Vec<Encoding>>
+    where
+        A: AsRef<str> + Sync,
+        B: AsRef<str> + Sync,
+    {
+        self.pad(pairs.par_iter().map(|(first, second)| self.encode_pair(first.as_ref(), second.as_ref())))
+    }
+
+    /// Collect encodings, padded if [`Vocab::with_padding`] is set
+    fn pad<I: IndexedParallelIterator<Item = Result<Encoding>>>(&self, encodings: I) -> Result<Vec<Encoding>> {
+        let pad_id = match &self.padding {
+            Some(padding) => {
+                let id = self.token_to_id(padding.token()).ok_or_else(|| Error::Config {
+                    reason: format!("padding token {:?} is not in the vocabulary", padding.token()),
+                })?;
+                Some((padding, id))
+            }
+            None => None,
+        };
+
+        let mut encodings = encodings.collect::<Result<Vec<Encoding>>>()?;
+        if let Some((padding, id)) = pad_id {
+            padding.apply(&mut encodings, id);
+        }
+
+        Ok(encodings)
+    }
+
+    /// Normalize and split text into terms
+    pub(crate) fn terms(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(&self.normalizer.normalize(text))
+    }
+
+    /// Encode already tokenized terms as token ids
+    ///
+    /// Each term is split by the vocabulary's [`Model`]. Pieces that are
+    /// not in the vocabulary map to the unknown token, or are skipped if
+    /// the vocabulary has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - terms to look up
+    pub fn encode_tokens(&self, tokens: &[String]) -> Vec<i32> {
+        self.model.encode_terms(tokens, |piece| self.token_to_id(piece), self.unk)
+    }
+
+    /// Decode token ids back into vocabulary terms
+    ///
+    /// Ids that are not in the vocabulary are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - token ids to look up
+    pub fn decode(&self, ids: &[i32]) -> Vec<String> {
+        ids.iter()
+           .filter_map(|&id| self.id_to_token(id))
+           .map(|s| s.to_owned())
+           .collect()
+    }
+
+    /// Decode token ids back into the bytes they stand for
+    ///
+    /// Pieces of a byte-level model become the bytes they spell, and
+    /// any other entry its own UTF-8, so byte-level vocabularies that
+    /// keep whitespace and case give back their input exactly. Ids that
+    /// are not in the vocabulary are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - token ids to look up
+    pub fn decode_bytes(&self, ids: &[i32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for term in ids.iter().filter_map(|&id| self.id_to_token(id)) {
+            if self.is_special(term) {
+                bytes.extend_from_slice(term.as_bytes());
+            } else {
+                self.model.piece_bytes(term, &mut bytes);
+            }
+        }
+
+        bytes
+    }
 }
 
-#[pymodule]
-fn tok(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
-    m.add_class::<Vocab>()?;
-    Ok(())
+impl Encoder for Vocab {
+    fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        &*self.tokenizer
+    }
+
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    fn unk_id(&self) -> Option<i32> {
+        self.unk
+    }
+
+    fn token_to_id(&self, term: &str) -> Option<i32> {
+        Vocab::token_to_id(self, term)
+    }
+
+    fn id_to_token(&self, id: i32) -> Option<&str> {
+        Vocab::id_to_token(self, id)
+    }
+
+    fn is_special(&self, term: &str) -> bool {
+        Vocab::is_special(self, term)
+    }
+
+    fn template(&self) -> Option<&Template> {
+        self.template.as_ref()
+    }
+
+    fn truncation(&self) -> Option<&Truncation> {
+        self.truncation.as_ref()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use proptest::prelude::*;
+
+    fn saved(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn load(contents: &str) -> Result<Vocab> {
+        let file = saved(contents);
+        Vocab::load(file.path().to_str().unwrap())
+    }
+
+    fn build(text: &str) -> Vocab {
+        let file = saved(text);
+        Vocab::new(file.path().to_str().unwrap()).unwrap()
+    }
+
+    fn round_trip(vocab: &Vocab) -> (Vocab, String) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        vocab.write(path).unwrap();
+
+        (Vocab::load(path).unwrap(), std::fs::read_to_string(path).unwrap())
+    }
+
+    fn build_with_specials(text: &str) -> Vocab {
+        let file = saved(text);
+        Vocab::builder().special_tokens(&[PAD, BOS, EOS])
+                        .unk_token(UNK)
+                        .build(file.path().to_str().unwrap())
+                        .unwrap()
+    }
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn tokenize_lowercases_and_strips_punctuation() {
+        let tokens = Vocab::tokenize("Hello, world! Don't panic.".to_string());
+        assert_eq!(tokens, vec!["hello", "world", "don't", "panic"]);
+    }
+
+    #[test]
+    fn lowercasing_does_not_split_words() {
+        assert_eq!(Vocab::tokenize("İstanbul".to_string()), vec!["i\u{307}stanbul"]);
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        match Vocab::new("/definitely/not/here.txt") {
+            Err(Error::Io { path, source }) => {
+                assert_eq!(path.to_str(), Some("/definitely/not/here.txt"));
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("expected Io error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_reports_line_without_tab() {
+        match load("a\t0\nb 1\n") {
+            Err(Error::Parse { line, text, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(text, "b 1");
+            }
+            other => panic!("expected Parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_reports_non_integer_token() {
+        assert!(matches!(load("a\tzero\n"), Err(Error::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn load_rejects_duplicates() {
+        assert!(matches!(load("a\t0\na\t1\n"), Err(Error::DuplicateTerm { line: 2, .. })));
+        assert!(matches!(load("a\t0\nb\t0\n"), Err(Error::DuplicateId { line: 2, id: 0 })));
+    }
+
+    #[test]
+    fn load_skips_blank_lines() {
+        let vocab = load("a\t0\n\nb\t1\n").unwrap();
+        assert_eq!(vocab.size(), 2);
+    }
+
+    #[test]
+    fn encode_follows_first_seen_order() {
+        let vocab = build("The cat sat on the mat.");
+        assert_eq!(vocab.encode("the mat, the cat"), vec![0, 4, 0, 1]);
+        assert_eq!(vocab.token_to_id("sat"), Some(2));
+        assert_eq!(vocab.id_to_token(3), Some("on"));
+    }
+
+    #[test]
+    fn encode_skips_unknown_terms() {
+        let vocab = build("a b c");
+        assert_eq!(vocab.encode("a z c"), vec![0, 2]);
+        assert_eq!(vocab.token_to_id("z"), None);
+    }
+
+    #[test]
+    fn decode_inverts_encode() {
+        let vocab = build("Don't stop me now");
+        let ids = vocab.encode("now don't stop");
+        assert_eq!(vocab.decode(&ids), vec!["now", "don't", "stop"]);
+        assert_eq!(vocab.decode(&[42]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn loaded_vocab_has_reverse_index() {
+        let vocab = load("a\t7\nb\t3\n").unwrap();
+        assert_eq!(vocab.decode(&[3, 7]), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn loaded_vocab_encodes_with_given_tokenizer() {
+        let vocab = load("a-b\t0\nc\t1\n").unwrap();
+        assert_eq!(vocab.encode("A-B c"), vec![1]);
+
+        let vocab = vocab.with_tokenizer(|text: &str| -> Vec<String> {
+            text.split(' ').map(str::to_owned).collect()
+        });
+        assert_eq!(vocab.encode("A-B c"), vec![0, 1]);
+    }
+
+    #[test]
+    fn normalizer_is_used_for_encoding_and_saved() {
+        let normalizer = Normalizer::new().unicode(crate::UnicodeForm::Nfc);
+        let vocab = Vocab::builder().normalizer(normalizer.clone())
+                                    .build_reader("Cafe\u{301} café".as_bytes())
+                                    .unwrap();
+        assert_eq!(vocab.entries(), vec![("Café", 0), ("café", 1)]);
+        assert_eq!(vocab.encode("cafe\u{301} CAFÉ"), vec![1]);
+
+        let (loaded, _) = round_trip(&vocab);
+        assert_eq!(loaded.normalizer(), &normalizer);
+        assert_eq!(loaded.encode("Cafe\u{301}"), vec![0]);
+
+        let loaded = Vocab::from_binary(&vocab.to_binary()).unwrap();
+        assert_eq!(loaded, vocab);
+        assert_ne!(loaded, vocab.clone().with_normalizer(Normalizer::default()));
+    }
+
+    #[test]
+    fn built_in_tokenizers_are_saved() {
+        use crate::tokenizer::{RegexTokenizer, SplitDigits, WhitespaceTokenizer};
+
+        let text = "hello, world. route 66";
+        let tokenizers = [SharedTokenizer::new(WhitespaceTokenizer),
+                          SharedTokenizer::new(RegexTokenizer::gpt2()),
+                          SharedTokenizer::new(RegexTokenizer::new(r"\w+|[^\w\s]").unwrap()),
+                          SharedTokenizer::new(SplitDigits(WhitespaceTokenizer))];
+        for tokenizer in &tokenizers {
+            let vocab = Vocab::builder().tokenizer(tokenizer.clone())
+                                        .build_reader(text.as_bytes())
+                                        .unwrap();
+            assert_eq!(vocab.encode(text).len(), tokenizer.tokenize(text).len());
+            let (loaded, _) = round_trip(&vocab);
+            assert_eq!(loaded.encode(text), vocab.encode(text));
+            let loaded = Vocab::from_binary(&vocab.to_binary()).unwrap();
+            assert_eq!(loaded.encode(text), vocab.encode(text));
+        }
+
+        let custom = Vocab::builder().tokenizer(|text: &str| vec![text.to_owned()])
+                                     .build_reader("a b".as_bytes())
+                                     .unwrap();
+        assert!(!tsv::render(&custom).contains("#tokenizer"));
+        assert!(!tsv::render(&build("a b")).contains("#tokenizer"));
+    }
+
+    #[test]
+    fn special_tokens_take_lowest_ids() {
+        let vocab = build_with_specials("hello world");
+        assert_eq!(vocab.special_tokens(), &[PAD, BOS, EOS, UNK]);
+        assert_eq!(vocab.token_to_id(PAD), Some(0));
+        assert_eq!(vocab.unk_id(), Some(3));
+        assert_eq!(vocab.token_to_id("hello"), Some(4));
+        assert!(vocab.is_special(BOS));
+        assert!(!vocab.is_special("hello"));
+    }
+
+    #[test]
+    fn encode_maps_unknown_terms_to_unk() {
+        let vocab = build_with_specials("a b c");
+        assert_eq!(vocab.encode("a z c"), vec![4, 3, 6]);
+    }
+
+    #[test]
+    fn specials_survive_write_and_load() {
+        let vocab = build_with_specials("a b c");
+        assert_eq!(round_trip(&vocab).0, vocab);
+    }
+
+    #[test]
+    fn write_sorts_lines_by_token() {
+        let vocab = build_with_specials("zebra apple mango apple");
+        let (_, contents) = round_trip(&vocab);
+        assert_eq!(contents, "#tok-vocab 2\tterm\tid\tflag\tfreq\n#normalizer\t[\"lowercase\"]\n\
+                              #model\t{\"type\":\"word\"}\n<pad>\t0\tspecial\t0\n<bos>\t1\tspecial\t0\n<eos>\t2\tspecial\t0\n\
+                              <unk>\t3\tunk\t0\nzebra\t4\t\t1\napple\t5\t\t2\nmango\t6\t\t1\n");
+        assert_eq!(round_trip(&vocab).1, contents);
+    }
+
+    #[test]
+    fn load_detects_json() {
+        let vocab = build("the cat sat");
+        let dir = tempfile::tempdir().unwrap();
+
+        let named = dir.path().join("vocab.json");
+        vocab.write_json(named.to_str().unwrap()).unwrap();
+        assert_eq!(Vocab::load(named.to_str().unwrap()).unwrap(), vocab);
+        assert_eq!(Vocab::load_json(named.to_str().unwrap()).unwrap(), vocab);
+
+        let sniffed = dir.path().join("vocab");
+        vocab.write_json(sniffed.to_str().unwrap()).unwrap();
+        assert_eq!(Vocab::load(sniffed.to_str().unwrap()).unwrap(), vocab);
+
+        let tsv = dir.path().join("vocab.tsv");
+        std::fs::write(&tsv, "{\t0\n").unwrap();
+        assert_eq!(Vocab::load(tsv.to_str().unwrap()).unwrap().token_to_id("{"), Some(0));
+
+        let headerless = dir.path().join("braces");
+        std::fs::write(&headerless, "{\t0\n}\t1\n").unwrap();
+        assert_eq!(Vocab::load(headerless.to_str().unwrap()).unwrap().token_to_id("}"), Some(1));
+    }
+
+    #[test]
+    fn load_detects_binary() {
+        let vocab = build_with_specials("the cat sat");
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        vocab.write_binary(path).unwrap();
+
+        assert_eq!(Vocab::load(path).unwrap(), vocab);
+        assert_eq!(Vocab::load_binary(path).unwrap(), vocab);
+        assert_eq!(Vocab::from_binary(&vocab.to_binary()).unwrap(), vocab);
+    }
+
+    #[test]
+    fn json_drops_special_flags() {
+        let vocab = build_with_specials("a b");
+        let loaded = Vocab::from_json(&vocab.to_json()).unwrap();
+        assert_eq!(loaded.entries(), vocab.entries());
+        assert_eq!(loaded.unk_id(), None);
+    }
+
+    #[test]
+    fn empty_vocab_round_trips() {
+        let vocab = build("  ...  ");
+        let (loaded, contents) = round_trip(&vocab);
+        assert_eq!(vocab.size(), 0);
+        assert_eq!(contents, "#tok-vocab 2\tterm\tid\tflag\n#normalizer\t[\"lowercase\"]\n#model\t{\"type\":\"word\"}\n");
+        assert_eq!(loaded, vocab);
+    }
+
+    proptest! {
+        #[test]
+        fn built_vocab_round_trips(text in "(\\PC|[ \t\n'])*", specials in any::<bool>()) {
+            let mut builder = Vocab::builder();
+            if specials {
+                builder = builder.special_tokens(&[PAD, BOS]).unk_token(UNK);
+            }
+            let vocab = builder.build_reader(text.as_bytes()).unwrap();
+            let (loaded, _) = round_trip(&vocab);
+            prop_assert_eq!(&loaded, &vocab);
+            prop_assert_eq!(loaded.encode(&text), vocab.encode(&text));
+        }
+
+        #[test]
+        fn write_is_deterministic(terms in prop::collection::vec("[a-zà-ÿ']{1,8}", 0..40)) {
+            let text = terms.join(" ");
+            let first = round_trip(&build(&text)).1;
+            let second = round_trip(&build(&text)).1;
+            prop_assert_eq!(first, second);
+        }
+    }
+
+    #[test]
+    fn load_rejects_unknown_flag_and_second_unk() {
+        assert!(matches!(load("a\t0\tbogus\n"), Err(Error::Parse { line: 1, .. })));
+        assert!(matches!(load("a\t0\tunk\nb\t1\tunk\n"), Err(Error::Parse { line: 2, .. })));
+    }
 }